@@ -1,166 +1,79 @@
-#import bevy_pbr::mesh_view_bindings
-#import bevy_pbr::pbr_types
-#import bevy_pbr::mesh_bindings
-
-#import bevy_pbr::utils
-#import bevy_pbr::clustered_forward
-#import bevy_pbr::lighting
-#import bevy_pbr::shadows
-#import bevy_pbr::pbr_functions
+#import bevy_pbr::{
+    mesh_functions::{get_model_matrix, mesh_position_local_to_world},
+    view_transformations::position_world_to_clip,
+}
 
-@group(1) @binding(0)
+@group(2) @binding(0)
 var chunk_texture: texture_2d_array<f32>;
 
-@group(1) @binding(1)
+@group(2) @binding(1)
 var chunk_sampler: sampler;
 
-@group(1) @binding(2)
-var pbr_texture: texture_2d_array<f32>;
-
-@group(1) @binding(3)
-var pbr_sampler: sampler;
-
-// NOTE: Bindings must come before functions that use them!
-#import bevy_pbr::mesh_functions
+// Must match `NO_OVERLAY` in material.rs
+const NO_OVERLAY: u32 = 4294967295u;
+// Brightness of complete darkness, so caves are not pitch black
+const MIN_BRIGHTNESS: f32 = 0.05;
+// Brightness of a fully occluded corner
+const MIN_AO: f32 = 0.4;
 
 struct Vertex {
+    @builtin(instance_index) instance_index: u32,
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
-#ifdef VERTEX_UVS
     @location(2) uv: vec2<f32>,
-#endif
-#ifdef VERTEX_TANGENTS
-    @location(3) tangent: vec4<f32>,
-#endif
-#ifdef VERTEX_COLORS
-    @location(4) color: vec4<f32>,
-#endif
-#ifdef SKINNED
-    @location(5) joint_indices: vec4<u32>,
-    @location(6) joint_weights: vec4<f32>,
-#endif
+    @location(3) tint: vec4<f32>,
     @location(7) base_indice: u32,
     @location(8) overlay_indice: u32,
+    @location(9) light: vec2<f32>,
+    @location(10) ao: f32,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    #import bevy_pbr::mesh_vertex_output
-    
-    @location(5) base_indice: u32,
-    @location(6) overlay_indice: u32,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) base_indice: u32,
+    @location(2) @interpolate(flat) overlay_indice: u32,
+    @location(3) tint: vec4<f32>,
+    @location(4) light: vec2<f32>,
+    @location(5) ao: f32,
 };
 
+// Every light level is 80% as bright as the next one
+fn brightness(light: vec2<f32>) -> f32 {
+    let level = max(light.x, light.y);
+    return mix(MIN_BRIGHTNESS, 1.0, pow(0.8, (1.0 - level) * 15.0));
+}
+
 @vertex
 fn vertex(vertex: Vertex) -> VertexOutput {
     var out: VertexOutput;
-#ifdef SKINNED
-    var model = skin_model(vertex.joint_indices, vertex.joint_weights);
-    out.world_normal = skin_normals(model, vertex.normal);
-#else
-    var model = mesh.model;
-    out.world_normal = mesh_normal_local_to_world(vertex.normal);
-#endif
-    out.world_position = mesh_position_local_to_world(model, vec4<f32>(vertex.position, 1.0));
-#ifdef VERTEX_UVS
-    out.uv = vertex.uv;
-#endif
-#ifdef VERTEX_TANGENTS
-    out.world_tangent = mesh_tangent_local_to_world(model, vertex.tangent);
-#endif
-#ifdef VERTEX_COLORS
-    out.color = vertex.color;
-#endif
-    out.clip_position = mesh_position_world_to_clip(out.world_position);
 
+    let model = get_model_matrix(vertex.instance_index);
+    let world_position = mesh_position_local_to_world(model, vec4<f32>(vertex.position, 1.0));
+    out.clip_position = position_world_to_clip(world_position.xyz);
+
+    out.uv = vertex.uv;
     out.base_indice = vertex.base_indice;
     out.overlay_indice = vertex.overlay_indice;
+    out.tint = vertex.tint;
+    out.light = vertex.light;
+    out.ao = vertex.ao;
 
     return out;
 }
 
-struct FragmentInput {
-    @builtin(front_facing) is_front: bool,
-    @builtin(position) frag_coord: vec4<f32>,
-    #import bevy_pbr::mesh_vertex_output
-    
-    @location(5) base_indice: u32,
-    @location(6) overlay_indice: u32,
-};
-
 @fragment
-fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
-    var pbr_input: PbrInput = pbr_input_new();
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Sampling has to happen in uniform control flow,
+    // so the overlay is always sampled and then discarded if absent
+    let b = textureSample(chunk_texture, chunk_sampler, in.uv, in.base_indice);
+    var o = textureSample(chunk_texture, chunk_sampler, in.uv, in.overlay_indice) * in.tint;
+    o.a = select(o.a, 0.0, in.overlay_indice == NO_OVERLAY);
 
     // base color with overlay
 
-    let b = textureSample(chunk_texture, chunk_sampler, in.uv, i32(in.base_indice));
-    let o = textureSample(chunk_texture, chunk_sampler, in.uv, i32(in.overlay_indice));
-
-    let bg_r = b.r * b.a;
-    let bg_g = b.g * b.a;
-    let bg_b = b.b * b.a;
-
-    let fg_r = o.r * o.a;
-    let fg_g = o.g * o.a;
-    let fg_b = o.b * o.a;
-
-    let color_r = fg_r + bg_r * (1.0 - o.a);
-    let color_g = fg_g + bg_g * (1.0 - o.a);
-    let color_b = fg_b + bg_b * (1.0 - o.a);
+    let color = (o.rgb * o.a + b.rgb * (1.0 - o.a)) * brightness(in.light) * mix(MIN_AO, 1.0, in.ao);
+    let alpha = o.a + b.a * (1.0 - o.a);
 
-    let color = vec4<f32>(color_r, color_g, color_b, 1.0);
-
-    pbr_input.material.base_color = color;
-
-    // pbr values with overlay
-
-    let pbr_b = textureSample(pbr_texture, pbr_sampler, in.uv, i32(in.base_indice));
-    let pbr_o = textureSample(pbr_texture, pbr_sampler, in.uv, i32(in.overlay_indice));
-
-    let pbr_bg_r = pbr_b.r * b.a;
-    let pbr_bg_g = pbr_b.g * b.a;
-    let pbr_bg_b = pbr_b.b * b.a;
-
-    let pbr_fg_r = pbr_o.r * o.a;
-    let pbr_fg_g = pbr_o.g * o.a;
-    let pbr_fg_b = pbr_o.b * o.a;
-
-    let pbr_r = pbr_fg_r + pbr_bg_r * (1.0 - o.a);
-    let pbr_g = pbr_fg_g + pbr_bg_g * (1.0 - o.a);
-    let pbr_b = pbr_fg_b + pbr_bg_b * (1.0 - o.a);
-
-    pbr_input.material.perceptual_roughness = pbr_r;
-    pbr_input.material.metallic = pbr_g;
-    pbr_input.material.reflectance = pbr_b;
-
-#ifdef VERTEX_COLORS
-    pbr_input.material.base_color = pbr_input.material.base_color * in.color;
-#endif
-
-    pbr_input.frag_coord = in.frag_coord;
-    pbr_input.world_position = in.world_position;
-    pbr_input.world_normal = prepare_world_normal(
-        in.world_normal,
-        false,
-        in.is_front,
-    );
-
-    pbr_input.is_orthographic = view.projection[3].w == 1.0;
-
-    pbr_input.N = apply_normal_mapping(
-        pbr_input.material.flags,
-        pbr_input.world_normal,
-#ifdef VERTEX_TANGENTS
-#ifdef STANDARDMATERIAL_NORMAL_MAP
-        in.world_tangent,
-#endif
-#endif
-#ifdef VERTEX_UVS
-        in.uv,
-#endif
-    );
-    pbr_input.V = calculate_view(in.world_position, pbr_input.is_orthographic);
-
-    return pbr(pbr_input);
-}
\ No newline at end of file
+    return vec4<f32>(color, alpha);
+}
@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use super::block::Block;
+
+/// Distance between climate samples averaged for terrain height,
+/// so that hills do not end with a cliff on biome borders
+const BLEND_DISTANCE: i32 = 8;
+
+/// Features placed on top of base terrain
+#[derive(Clone, Copy, Debug)]
+pub struct Decorations {
+    /// Chance of a tree growing on a surface block
+    pub tree_chance: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Reflect)]
+pub enum Biome {
+    Plains,
+    Forest,
+    Desert,
+    Snowy,
+}
+
+impl Biome {
+    /// Picks a biome from temperature and humidity, both roughly in -1..1
+    fn from_climate(temperature: f64, humidity: f64) -> Self {
+        if temperature < -0.25 {
+            Self::Snowy
+        } else if temperature > 0.25 && humidity < 0.0 {
+            Self::Desert
+        } else if humidity > 0.15 {
+            Self::Forest
+        } else {
+            Self::Plains
+        }
+    }
+
+    /// Topmost block of a column
+    pub fn surface(&self) -> Block {
+        match self {
+            Self::Plains | Self::Forest => Block::GRASS,
+            Self::Desert => Block::SAND,
+            Self::Snowy => Block::SNOW,
+        }
+    }
+
+    /// Blocks between the surface and stone
+    pub fn filler(&self) -> Block {
+        match self {
+            Self::Plains | Self::Forest | Self::Snowy => Block::DIRT,
+            Self::Desert => Block::SAND,
+        }
+    }
+
+    /// How far terrain goes up and down from its base height
+    pub fn height_scale(&self) -> f64 {
+        match self {
+            Self::Plains => 8.0,
+            Self::Forest => 14.0,
+            Self::Desert => 5.0,
+            Self::Snowy => 20.0,
+        }
+    }
+
+    pub fn decorations(&self) -> Decorations {
+        let tree_chance = match self {
+            Self::Plains => 0.002,
+            Self::Forest => 0.03,
+            Self::Desert => 0.0,
+            Self::Snowy => 0.005,
+        };
+        Decorations { tree_chance }
+    }
+
+    /// Color multiplied with overlay textures, e.g. grass
+    pub fn tint(&self) -> [f32; 4] {
+        match self {
+            Self::Plains => [1.0, 1.0, 1.0, 1.0],
+            Self::Forest => [0.75, 0.95, 0.7, 1.0],
+            Self::Desert => [1.2, 1.1, 0.65, 1.0],
+            Self::Snowy => [0.8, 0.95, 1.05, 1.0],
+        }
+    }
+}
+
+/// Temperature and humidity noise selecting a [`Biome`] per column
+pub struct BiomeMap {
+    temperature: Fbm<Perlin>,
+    humidity: Fbm<Perlin>,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            temperature: Fbm::<Perlin>::new(seed.wrapping_add(2))
+                .set_octaves(2)
+                .set_frequency(1.0 / 512.0),
+            humidity: Fbm::<Perlin>::new(seed.wrapping_add(3))
+                .set_octaves(2)
+                .set_frequency(1.0 / 384.0),
+        }
+    }
+
+    pub fn biome(&self, x: i32, z: i32) -> Biome {
+        let point = [x as f64, z as f64];
+        Biome::from_climate(self.temperature.get(point), self.humidity.get(point))
+    }
+
+    /// [`Biome::height_scale`] averaged with nearby columns
+    pub fn height_scale(&self, x: i32, z: i32) -> f64 {
+        let offsets = [
+            (0, 0),
+            (-BLEND_DISTANCE, 0),
+            (BLEND_DISTANCE, 0),
+            (0, -BLEND_DISTANCE),
+            (0, BLEND_DISTANCE),
+        ];
+        let sum: f64 = offsets.iter()
+            .map(|(dx, dz)| self.biome(x + dx, z + dz).height_scale())
+            .sum();
+        sum / offsets.len() as f64
+    }
+}
@@ -1,33 +1,59 @@
-#[derive(Clone, Copy, PartialEq, Eq)]
+use serde::Deserialize;
+use super::face::Side;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Deserialize)]
 pub enum Visibility {
+    #[default]
     Empty,
     Transparent,
     Opaque
 }
 
-pub trait Voxel: Eq {
+pub trait Voxel: Copy + Eq + Default {
     fn visibility(&self) -> Visibility;
     fn visible(&self, other: &Self) -> bool;
-    fn texture(&self) -> Option<&'static str>;
+    /// Texture array layer of the given side of the voxel
+    fn texture(&self, side: Side) -> Option<u32>;
+    /// Layer drawn on top of [`Voxel::texture`], e.g. with transparency
+    fn overlay(&self, _side: Side) -> Option<u32> {
+        None
+    }
+}
+
+/// Id of a block in [`super::registry::BlockRegistry`]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Block(pub u16);
+
+/// Blocks the game itself relies on,
+/// their ids must match `assets/blocks.ron`
+impl Block {
+    pub const AIR: Self = Self(0);
+    pub const DIRT: Self = Self(1);
+    pub const STONE: Self = Self(2);
+    pub const GRASS: Self = Self(4);
+    pub const SAND: Self = Self(5);
+    pub const SNOW: Self = Self(6);
+    pub const WATER: Self = Self(7);
+    pub const LOG: Self = Self(8);
+    pub const LEAVES: Self = Self(9);
+    pub const COAL_ORE: Self = Self(10);
 }
 
+/// [`Block`] with everything needed for meshing,
+/// see [`super::registry::BlockInfos`]
 #[derive(Default, Clone, Copy, PartialEq, Eq)]
-pub enum Block {
-    #[default]
-    Air,
-    Dirt,
-    Stone,
-    Glass
+pub struct BlockInfo {
+    pub block: Block,
+    pub visibility: Visibility,
+    /// Texture layers indexed by [`Side`]
+    pub textures: [Option<u32>; 6],
+    /// Overlay layers indexed by [`Side`]
+    pub overlays: [Option<u32>; 6],
 }
 
-impl Voxel for Block {
+impl Voxel for BlockInfo {
     fn visibility(&self) -> Visibility {
-        match self {
-            Self::Air => Visibility::Empty,
-            Self::Dirt => Visibility::Opaque,
-            Self::Stone => Visibility::Opaque,
-            Self::Glass => Visibility::Transparent
-        }
+        self.visibility
     }
 
     fn visible(&self, other: &Self) -> bool {
@@ -39,19 +65,18 @@ impl Voxel for Block {
             (Visibility::Transparent, Visibility::Empty) => true,
 
             (Visibility::Transparent, Visibility::Transparent) => {
-                self != other
+                self.block != other.block
             },
 
             _ => false
         }
     }
 
-    fn texture(&self) -> Option<&'static str> {
-        Some(match self {
-            Self::Dirt => "dirt.png",
-            Self::Stone => "stone.png",
-            Self::Glass => "glass.png",
-            _ => return None,
-        })
+    fn texture(&self, side: Side) -> Option<u32> {
+        self.textures[usize::from(side)]
     }
-}
\ No newline at end of file
+
+    fn overlay(&self, side: Side) -> Option<u32> {
+        self.overlays[usize::from(side)]
+    }
+}
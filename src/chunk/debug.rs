@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+use crate::keybinds::KeyBindsResource;
+use super::{Chunk, MyChunk};
+use super::loader::{ChunkLoaded, ChunkRefreshed, ChunkState, ChunkUnloaded, CurrentChunk};
+use super::mesh::{line_box, VOXEL_SIZE};
+
+/// Which chunks get their borders drawn
+#[derive(Resource, Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[reflect(Resource)]
+pub enum ChunkBorders {
+    #[default]
+    Hidden,
+    /// Only the chunk the player is in
+    Current,
+    All,
+}
+
+impl ChunkBorders {
+    fn next(self) -> Self {
+        match self {
+            Self::Hidden => Self::Current,
+            Self::Current => Self::All,
+            Self::All => Self::Hidden,
+        }
+    }
+
+    fn visibility(self, position: IVec3, current: IVec3) -> Visibility {
+        match self {
+            Self::All => Visibility::Visible,
+            Self::Current if position == current => Visibility::Visible,
+            _ => Visibility::Hidden,
+        }
+    }
+}
+
+/// Lines around a chunk, child of the chunk entity
+#[derive(Component)]
+struct ChunkBorder(IVec3);
+
+#[derive(Resource)]
+struct ChunkBorderAssets {
+    mesh: Handle<Mesh>,
+    /// Line colors indexed by [`ChunkState`]
+    materials: [Handle<StandardMaterial>; 4],
+}
+
+impl ChunkBorderAssets {
+    fn material(&self, state: ChunkState) -> Handle<StandardMaterial> {
+        let index = match state {
+            ChunkState::Loading => 0,
+            ChunkState::Unloading => 1,
+            ChunkState::Refreshing => 2,
+            ChunkState::Loaded => 3,
+        };
+        self.materials[index].clone()
+    }
+}
+
+pub struct ChunkDebugPlugin;
+
+impl Plugin for ChunkDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ChunkBorders>()
+            .register_type::<ChunkBorders>()
+            .add_systems(Startup, setup_chunk_border_assets)
+            // Chunks are despawned during `Update`, borders must not be added to them after that
+            .add_systems(PostUpdate, (
+                toggle_chunk_borders,
+                spawn_chunk_borders,
+                update_chunk_borders
+            ).chain())
+            .add_systems(Update, log_chunk_events);
+    }
+}
+
+fn setup_chunk_border_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>
+) {
+    let mut line_material = |color: Color| materials.add(StandardMaterial {
+        base_color: color,
+        unlit: true,
+        ..default()
+    });
+    commands.insert_resource(ChunkBorderAssets {
+        mesh: meshes.add(line_box(Vec3::splat(MyChunk::SIDE as f32 * VOXEL_SIZE))),
+        materials: [
+            line_material(Color::YELLOW),
+            line_material(Color::RED),
+            line_material(Color::CYAN),
+            line_material(Color::GREEN),
+        ],
+    });
+}
+
+fn toggle_chunk_borders(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keybinds: Res<KeyBindsResource>,
+    mut borders: ResMut<ChunkBorders>
+) {
+    if keyboard_input.just_pressed(keybinds.toggle_chunk_borders) {
+        *borders = borders.next();
+    }
+}
+
+fn spawn_chunk_borders(
+    mut commands: Commands,
+    chunks_query: Query<(Entity, &Transform, &ChunkState), Added<ChunkState>>,
+    assets: Res<ChunkBorderAssets>,
+    borders: Res<ChunkBorders>,
+    current_chunk: Res<CurrentChunk>
+) {
+    for (entity, transform, state) in chunks_query.iter() {
+        let position = (transform.translation / (MyChunk::SIDE as f32 * VOXEL_SIZE))
+            .round()
+            .as_ivec3();
+        let border = commands.spawn((
+            ChunkBorder(position),
+            PbrBundle {
+                mesh: assets.mesh.clone(),
+                material: assets.material(*state),
+                visibility: borders.visibility(position, current_chunk.0),
+                ..default()
+            },
+            Name::new("Chunk border")
+        )).id();
+        commands.entity(entity).add_child(border);
+    }
+}
+
+fn update_chunk_borders(
+    mut borders_query: Query<(&ChunkBorder, &Parent, &mut Handle<StandardMaterial>, &mut Visibility)>,
+    states_query: Query<&ChunkState>,
+    assets: Res<ChunkBorderAssets>,
+    borders: Res<ChunkBorders>,
+    current_chunk: Res<CurrentChunk>
+) {
+    for (border, parent, mut material, mut visibility) in borders_query.iter_mut() {
+        visibility.set_if_neq(borders.visibility(border.0, current_chunk.0));
+        if let Ok(state) = states_query.get(parent.get()) {
+            material.set_if_neq(assets.material(*state));
+        }
+    }
+}
+
+/// Traces the chunk lifecycle, shown with `RUST_LOG=bevycraft=debug`
+fn log_chunk_events(
+    mut loaded_events: EventReader<ChunkLoaded>,
+    mut refreshed_events: EventReader<ChunkRefreshed>,
+    mut unloaded_events: EventReader<ChunkUnloaded>
+) {
+    for ChunkLoaded(position) in loaded_events.read() {
+        debug!("chunk at {position} loaded");
+    }
+    for ChunkRefreshed(position) in refreshed_events.read() {
+        debug!("chunk at {position} refreshed");
+    }
+    for ChunkUnloaded(position) in unloaded_events.read() {
+        debug!("chunk at {position} unloaded");
+    }
+}
@@ -0,0 +1,126 @@
+use bevy::prelude::*;
+use super::{Chunk, ChunkMut, MyChunk, CHUNK_SIDE};
+use super::block::Block;
+
+pub const TREE_SALT: u64 = 1;
+pub const ORE_SALT: u64 = 2;
+
+const ORE_VEINS_PER_CHUNK: u64 = 4;
+pub const ORE_VEIN_LENGTH: usize = 8;
+/// Distance from the trunk to the farthest leaves
+pub const TREE_RADIUS: i32 = 2;
+
+/// Block placed by a decoration at a position in world coordinates
+pub type BlockWrite = (IVec3, Block);
+
+/// What a decoration block turns `current` into.
+/// The result does not depend on the order of writes,
+/// so chunks end up the same no matter in which order they are generated
+pub fn merge(current: Block, new: Block) -> Block {
+    match (current, new) {
+        (Block::AIR, Block::LOG | Block::LEAVES) => new,
+        (Block::LEAVES, Block::LOG) => new,
+        (Block::STONE, Block::COAL_ORE) => new,
+        _ => current,
+    }
+}
+
+/// Deterministic pseudo random number for a position, based on splitmix64
+pub fn hash(seed: u32, pos: IVec3, salt: u64) -> u64 {
+    let mut x = (seed as u64) ^ salt.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    for value in pos.to_array() {
+        x = x.wrapping_add(value as u32 as u64).wrapping_add(0x9E37_79B9_7F4A_7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^= x >> 31;
+    }
+    x
+}
+
+/// Maps a hash to 0..1
+pub fn chance(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Height of the topmost block of a tree above its root
+pub fn tree_top(height: i32) -> i32 {
+    height + 1
+}
+
+/// Blocks of a tree whose trunk starts at `root`
+pub fn tree(root: IVec3, height: i32) -> Vec<BlockWrite> {
+    let mut writes = Vec::new();
+    for y in 0..height {
+        writes.push((root + IVec3::new(0, y, 0), Block::LOG));
+    }
+    for y in height - 2..=height {
+        let radius = if y == height { 1 } else { TREE_RADIUS };
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                // Round the corners off
+                if x.abs() == radius && z.abs() == radius && radius > 1 {
+                    continue;
+                }
+                writes.push((root + IVec3::new(x, y, z), Block::LEAVES));
+            }
+        }
+    }
+    writes.push((root + IVec3::new(0, tree_top(height), 0), Block::LEAVES));
+    writes
+}
+
+/// Ore blocks along a random walk from `start`
+pub fn ore_vein(seed: u32, start: IVec3) -> Vec<BlockWrite> {
+    let mut pos = start;
+    let mut writes = Vec::with_capacity(ORE_VEIN_LENGTH);
+    for _ in 0..ORE_VEIN_LENGTH {
+        writes.push((pos, Block::COAL_ORE));
+        let step = hash(seed, pos, ORE_SALT);
+        let axis = (step % 3) as usize;
+        pos[axis] += if step & 8 == 0 { 1 } else { -1 };
+    }
+    writes
+}
+
+/// Starting points of ore veins of a chunk
+pub fn ore_veins(seed: u32, chunk_position: IVec3) -> impl Iterator<Item = IVec3> {
+    let origin = chunk_position * CHUNK_SIDE as i32;
+    (0..ORE_VEINS_PER_CHUNK).map(move |vein| {
+        let h = hash(seed, chunk_position, ORE_SALT + vein);
+        let side = CHUNK_SIDE as u64;
+        origin + IVec3::new(
+            (h % side) as i32,
+            (h / side % side) as i32,
+            (h / side / side % side) as i32
+        )
+    })
+}
+
+/// Merges writes that fall inside `chunk` into it, see [`merge`].
+/// The rest is left to the chunks they are in
+pub fn apply_writes(chunk: &mut MyChunk, writes: Vec<BlockWrite>) {
+    let origin = chunk.position * MyChunk::SIDE as i32;
+    for (pos, block) in writes {
+        let local = pos - origin;
+        if local.min_element() >= 0 && local.max_element() < MyChunk::SIDE as i32 {
+            let merged = merge(chunk.get(local), block);
+            chunk.set(local, merged);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_merge_in_any_order() {
+        let pos = IVec3::new(3, 4, 5);
+        let writes = [(pos, Block::LEAVES), (pos, Block::LOG)];
+        let (mut forward, mut backward) = (MyChunk::default(), MyChunk::default());
+        apply_writes(&mut forward, writes.to_vec());
+        apply_writes(&mut backward, writes.into_iter().rev().collect());
+        assert_eq!(forward.get(pos), Block::LOG);
+        assert_eq!(backward.get(pos), Block::LOG);
+    }
+}
@@ -1,7 +1,8 @@
 use bevy::prelude::*;
+use super::light::Light;
 
 /// Simply a direction, see [`Face`]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Side {
     Left,   // -x
     Right,  // +x
@@ -11,6 +12,17 @@ pub enum Side {
     Back,   // +z
 }
 
+impl Side {
+    pub const ALL: [Self; 6] = [
+        Self::Left,
+        Self::Right,
+        Self::Bottom,
+        Self::Top,
+        Self::Front,
+        Self::Back,
+    ];
+}
+
 impl From<usize> for Side {
     fn from(value: usize) -> Self {
         match value {
@@ -25,47 +37,82 @@ impl From<usize> for Side {
     }
 }
 
-impl Into<usize> for Side {
-    fn into(self) -> usize {
-        match self {
-            Self::Left   => 0,
-            Self::Right  => 1,
-            Self::Bottom => 2,
-            Self::Top    => 3,
-            Self::Front  => 4,
-            Self::Back   => 5,
+impl From<Side> for usize {
+    fn from(value: Side) -> Self {
+        match value {
+            Side::Left   => 0,
+            Side::Right  => 1,
+            Side::Bottom => 2,
+            Side::Top    => 3,
+            Side::Front  => 4,
+            Side::Back   => 5,
         }
     }
 }
 
-impl Into<Vec3> for Side {
-    fn into(self) -> Vec3 {
-        match self {
-            Self::Right  => Vec3::X,
-            Self::Left   => Vec3::NEG_X,
-            Self::Top    => Vec3::Y,
-            Self::Bottom => Vec3::NEG_Y,
-            Self::Back   => Vec3::Z,
-            Self::Front  => Vec3::NEG_Z,
+impl From<Side> for Vec3 {
+    fn from(value: Side) -> Self {
+        match value {
+            Side::Right  => Vec3::X,
+            Side::Left   => Vec3::NEG_X,
+            Side::Top    => Vec3::Y,
+            Side::Bottom => Vec3::NEG_Y,
+            Side::Back   => Vec3::Z,
+            Side::Front  => Vec3::NEG_Z,
         }
     }
 }
 
 impl Side {
-    pub fn indices(&self, start: u32) -> [u32; 6] {
-        [start, start + 2, start + 1, start + 1, start + 2, start + 3]
+    /// Two triangles split along the diagonal from vertex 1 to vertex 2,
+    /// or from vertex 0 to vertex 3 when `flipped`
+    pub fn indices(&self, start: u32, flipped: bool) -> [u32; 6] {
+        if flipped {
+            [start, start + 2, start + 3, start, start + 3, start + 1]
+        } else {
+            [start, start + 2, start + 1, start + 1, start + 2, start + 3]
+        }
     }
 
     pub fn normal(&self) -> [f32; 3] {
-        <Self as Into<Vec3>>::into(*self).to_array()
+        Vec3::from(*self).to_array()
+    }
+
+    /// Offset to the neighboring voxel this side is facing
+    pub fn offset(&self) -> IVec3 {
+        Vec3::from(*self).as_ivec3()
+    }
+
+    /// Axis this side is perpendicular to
+    pub fn axis(&self) -> usize {
+        match self {
+            Self::Left | Self::Right => 0,
+            Self::Bottom | Self::Top => 1,
+            Self::Front | Self::Back => 2,
+        }
+    }
+
+    /// Axes along which `u` and `v` texture coordinates grow
+    pub fn uv_axes(&self) -> (usize, usize) {
+        match self {
+            Self::Left | Self::Right => (2, 1),
+            Self::Top                => (2, 0),
+            Self::Bottom             => (0, 2),
+            Self::Front | Self::Back => (0, 1),
+        }
     }
 
     pub fn normals(&self) -> [[f32; 3]; 4] {
         [self.normal(); 4]
     }
 
+    /// `v = 0` is the top row of a texture,
+    /// so it goes on the upper corners of side faces
     pub fn uvs(&self) -> [[f32; 2]; 4] {
-        [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]]
+        match self {
+            Self::Top | Self::Bottom => [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]],
+            _                        => [[0.0, 1.0], [1.0, 1.0], [0.0, 0.0], [1.0, 0.0]],
+        }
     }
 
     pub fn positions(&self) -> [[f32; 3]; 4] {
@@ -80,52 +127,79 @@ impl Side {
     }
 }
 
-/// [`Side`] with position
+/// [`Side`] with position and texture.
+/// `size` is measured in voxels along [`Side::uv_axes`],
+/// it is bigger than one only for merged faces
 #[derive(Clone, Copy)]
 pub struct Face {
     pub position: UVec3,
     pub side: Side,
+    pub size: UVec2,
+    pub texture: Option<u32>,
+    pub overlay: Option<u32>,
+    /// Light of the voxel the face looks at
+    pub light: Light,
+    /// Ambient occlusion of each corner, from 0 (darkest) to 3,
+    /// in the order of [`Side::positions`]
+    pub ao: [u8; 4],
+    /// Face of a [`super::block::Visibility::Transparent`] voxel,
+    /// drawn in a separate blended mesh
+    pub transparent: bool,
 }
 
 impl Face {
+    /// The diagonal joins the brighter pair of corners,
+    /// otherwise occlusion is interpolated unevenly across the quad
     pub fn indices(&self, start: u32) -> [u32; 6] {
-        self.side.indices(start)
+        let [a, b, c, d] = self.ao;
+        self.side.indices(start, a + d > b + c)
     }
-  
+
     pub fn positions(&self, voxel_size: f32) -> [[f32; 3]; 4] {
-        let positions = self.side.positions();
-
-        let [x, y, z] = self.position.as_vec3().to_array();
-
-        [
-            [
-                (x + positions[0][0]) * voxel_size,
-                (y + positions[0][1]) * voxel_size,
-                (z + positions[0][2]) * voxel_size,
-            ],
-            [
-                (x + positions[1][0]) * voxel_size,
-                (y + positions[1][1]) * voxel_size,
-                (z + positions[1][2]) * voxel_size,
-            ],
-            [
-                (x + positions[2][0]) * voxel_size,
-                (y + positions[2][1]) * voxel_size,
-                (z + positions[2][2]) * voxel_size,
-            ],
-            [
-                (x + positions[3][0]) * voxel_size,
-                (y + positions[3][1]) * voxel_size,
-                (z + positions[3][2]) * voxel_size,
-            ],
-        ]
-    }
-  
+        let (u_axis, v_axis) = self.side.uv_axes();
+        let mut extent = Vec3::ONE;
+        extent[u_axis] = self.size.x as f32;
+        extent[v_axis] = self.size.y as f32;
+
+        let origin = self.position.as_vec3();
+
+        self.side.positions().map(|corner| {
+            ((origin + Vec3::from(corner) * extent) * voxel_size).to_array()
+        })
+    }
+
     pub fn normals(&self) -> [[f32; 3]; 4] {
         self.side.normals()
     }
-  
+
+    /// Texture coordinates repeat once per voxel
     pub fn uvs(&self) -> [[f32; 2]; 4] {
-        self.side.uvs()
+        let size = self.size.as_vec2();
+        self.side.uvs().map(|[u, v]| [u * size.x, v * size.y])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_textures_are_upright() {
+        for side in [Side::Left, Side::Right, Side::Front, Side::Back] {
+            let face = Face {
+                position: UVec3::ZERO,
+                side,
+                size: UVec2::new(1, 3),
+                texture: None,
+                overlay: None,
+                light: Light::default(),
+                ao: [3; 4],
+                transparent: false,
+            };
+            for (position, [_, v]) in face.positions(1.0).into_iter().zip(face.uvs()) {
+                // Top of the texture at the top of the face, repeated once per voxel
+                assert_eq!(v, 3.0 - position[1], "{side:?}");
+            }
+        }
     }
-}
\ No newline at end of file
+}
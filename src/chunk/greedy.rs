@@ -0,0 +1,222 @@
+use bevy::prelude::*;
+use super::{face_ao, is_hidden, Chunk};
+use super::face::{Face, Side};
+use super::block::{Visibility, Voxel};
+use super::light::Light;
+
+/// What has to be equal for two faces to be merged
+type MaskKey<T> = (T, Light, [u8; 4], Option<[f32; 4]>);
+
+/// Same as [`super::simple_mesh`], but neighboring faces
+/// of the same voxel, light, ambient occlusion and overlay `tint`
+/// lying in one plane are merged into rectangles
+pub fn greedy_mesh<C, T>(chunk: &C, tint: impl Fn(UVec3) -> [f32; 4]) -> Vec<Face>
+where
+    C: Chunk<Output = T>,
+    T: Voxel
+{
+    assert!(C::SIDE >= 2, "chunk side is too small");
+
+    let len = C::SIDE;
+    let mut buffer = Vec::new();
+    if is_hidden(chunk) {
+        return buffer;
+    }
+    // Visible faces of one slice, indexed by `u + v * len`
+    let mut mask: Vec<Option<MaskKey<T>>> = Vec::with_capacity(len * len);
+
+    for side in Side::ALL {
+        let axis = side.axis();
+        let (u_axis, v_axis) = side.uv_axes();
+
+        for layer in 0..len {
+            mask.clear();
+            for v in 0..len {
+                for u in 0..len {
+                    let mut pos = IVec3::ZERO;
+                    pos[axis] = layer as i32;
+                    pos[u_axis] = u as i32;
+                    pos[v_axis] = v as i32;
+
+                    let voxel = chunk.get(pos);
+                    let generate = voxel.visibility() != Visibility::Empty
+                        && voxel.visible(&chunk.get(pos + side.offset()));
+                    mask.push(generate.then(|| {
+                        // Only overlays are tinted, other faces merge across biomes
+                        let tint = voxel.overlay(side).map(|_| tint(pos.as_uvec3()));
+                        (voxel, chunk.light(pos + side.offset()), face_ao(chunk, pos, side), tint)
+                    }));
+                }
+            }
+
+            for v in 0..len {
+                let mut u = 0;
+                while u < len {
+                    let Some(key @ (voxel, light, ao, _)) = mask[u + v * len] else {
+                        u += 1;
+                        continue;
+                    };
+
+                    let mut width = 1;
+                    while u + width < len && mask[u + width + v * len] == Some(key) {
+                        width += 1;
+                    }
+
+                    let mut height = 1;
+                    'grow: while v + height < len {
+                        for k in 0..width {
+                            if mask[u + k + (v + height) * len] != Some(key) {
+                                break 'grow;
+                            }
+                        }
+                        height += 1;
+                    }
+
+                    for h in 0..height {
+                        for k in 0..width {
+                            mask[u + k + (v + h) * len] = None;
+                        }
+                    }
+
+                    let mut position = UVec3::ZERO;
+                    position[axis] = layer as u32;
+                    position[u_axis] = u as u32;
+                    position[v_axis] = v as u32;
+                    buffer.push(Face {
+                        position,
+                        side,
+                        size: UVec2::new(width as u32, height as u32),
+                        texture: voxel.texture(side),
+                        overlay: voxel.overlay(side),
+                        light,
+                        ao,
+                        transparent: voxel.visibility() == Visibility::Transparent,
+                    });
+
+                    u += width;
+                }
+            }
+        }
+    }
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{ChunkMut, MyChunk};
+    use super::super::block::{Block, BlockInfo};
+    use super::super::registry::BlockInfos;
+    use super::super::view::ChunkView;
+
+    /// Stone, and grass with a tinted overlay on top
+    fn infos() -> BlockInfos {
+        let mut infos = vec![BlockInfo::default(); Block::GRASS.0 as usize + 1];
+        infos[Block::STONE.0 as usize] = BlockInfo {
+            block: Block::STONE,
+            visibility: Visibility::Opaque,
+            textures: [Some(0); 6],
+            overlays: [None; 6],
+        };
+        let mut overlays = [None; 6];
+        overlays[usize::from(Side::Top)] = Some(2);
+        infos[Block::GRASS.0 as usize] = BlockInfo {
+            block: Block::GRASS,
+            visibility: Visibility::Opaque,
+            textures: [Some(1); 6],
+            overlays,
+        };
+        BlockInfos::new(infos)
+    }
+
+    /// Chunk filled with `block` below `height`
+    fn flat(height: usize, block: Block) -> MyChunk {
+        let mut chunk = MyChunk::default();
+        for i in 0..MyChunk::size() {
+            let (x, y, z) = MyChunk::delinearize(i);
+            if y < height {
+                chunk.set(IVec3::new(x as i32, y as i32, z as i32), block);
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn flat_chunk_has_one_quad_per_plane() {
+        let height = 4;
+        let view = ChunkView::new(flat(height, Block::STONE), Default::default(), infos());
+        let faces = greedy_mesh(&view, |_| [1.0; 4]);
+
+        assert_eq!(faces.len(), 6);
+        let side = MyChunk::SIDE as u32;
+        for face in faces {
+            let expected = match face.side {
+                Side::Top | Side::Bottom => UVec2::new(side, side),
+                _ => {
+                    // The vertical axis of side faces is y
+                    let (u_axis, _) = face.side.uv_axes();
+                    if u_axis == 1 {
+                        UVec2::new(height as u32, side)
+                    } else {
+                        UVec2::new(side, height as u32)
+                    }
+                }
+            };
+            assert_eq!(face.size, expected, "{:?}", face.side);
+        }
+    }
+
+    #[test]
+    fn faces_of_different_tints_are_not_merged() {
+        let view = ChunkView::new(flat(4, Block::GRASS), Default::default(), infos());
+        let faces = greedy_mesh(&view, |pos| if pos.x < 8 { [0.0; 4] } else { [1.0; 4] });
+
+        let tops: Vec<&Face> = faces.iter().filter(|face| face.side == Side::Top).collect();
+        assert_eq!(tops.len(), 2);
+        assert!(tops.iter().all(|face| face.size.x * face.size.y == 8 * MyChunk::SIDE as u32));
+        // Faces without an overlay ignore the tint
+        assert_eq!(faces.len(), 7);
+    }
+
+    #[test]
+    fn uniform_chunks_are_skipped() {
+        let stone = flat(MyChunk::SIDE, Block::STONE);
+        assert_eq!(stone.is_uniform(), Some(Block::STONE));
+        let enclosed = ChunkView::new(stone.clone(), std::array::from_fn(|_| Some(stone.clone())), infos());
+        assert!(is_hidden(&enclosed));
+        assert!(greedy_mesh(&enclosed, |_| [1.0; 4]).is_empty());
+
+        let air = ChunkView::new(MyChunk::default(), Default::default(), infos());
+        assert!(air.is_empty());
+        assert!(is_hidden(&air));
+
+        // Borders without neighbors are visible
+        let open = ChunkView::new(stone, Default::default(), infos());
+        assert!(!is_hidden(&open));
+    }
+
+    #[test]
+    fn occlusion_crosses_chunk_edges_and_corners() {
+        // Stone in the top corner of the chunk, and next to it in chunks across an edge and a corner
+        let mut center = MyChunk::default();
+        center.set(IVec3::splat(15), Block::STONE);
+        let mut edge = MyChunk::default();
+        edge.set(IVec3::new(0, 0, 15), Block::STONE);
+        let mut corner = MyChunk::default();
+        corner.set(IVec3::ZERO, Block::STONE);
+        let neighbor = |offset: IVec3| match offset.to_array() {
+            [1, 1, 0] => Some(&edge),
+            [1, 1, 1] => Some(&corner),
+            _ => None,
+        };
+
+        let plain = ChunkView::new(center.clone(), Default::default(), infos());
+        assert_eq!(face_ao(&plain, IVec3::splat(15), Side::Top), [3; 4]);
+
+        let view = ChunkView::new(center, Default::default(), infos()).with_diagonals(neighbor);
+        let mut ao = face_ao(&view, IVec3::splat(15), Side::Top);
+        ao.sort();
+        // Corner towards both neighbors, then the one only next to the edge
+        assert_eq!(ao, [1, 2, 3, 3]);
+    }
+}
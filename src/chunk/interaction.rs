@@ -0,0 +1,218 @@
+use bevy::prelude::*;
+use bevy::input::mouse::MouseWheel;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+use crate::keybinds::KeyBindsResource;
+use crate::player::{in_game_mode, GameMode, Player, PlayerCamera};
+use crate::physics::Collider;
+use super::block::{Block, Visibility};
+use super::raycast::{raycast, RayHit};
+use super::registry::BlockRegistry;
+use super::voxel_world::VoxelWorld;
+use super::outline::BlockOutlinePlugin;
+
+/// How far away blocks can be reached
+const REACH: f32 = 6.0;
+
+/// Block under the crosshair, updated every frame
+#[derive(Resource, Default)]
+pub struct TargetBlock(pub Option<RayHit>);
+
+/// Block placed with [`KeyBindsResource::place_block`]
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct SelectedBlock(pub u16);
+
+impl Default for SelectedBlock {
+    fn default() -> Self {
+        Self(Block::STONE.0)
+    }
+}
+
+/// Block being broken in survival, reset when the button is released
+/// or the crosshair moves to another block
+#[derive(Resource, Default)]
+pub struct BreakProgress {
+    pub position: Option<IVec3>,
+    /// Seconds spent breaking the block
+    pub elapsed: f32,
+}
+
+pub struct BlockInteractionPlugin;
+
+impl Plugin for BlockInteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_plugins(BlockOutlinePlugin)
+            .init_resource::<TargetBlock>()
+            .init_resource::<SelectedBlock>()
+            .init_resource::<BreakProgress>()
+            .register_type::<SelectedBlock>()
+            .add_systems(Update, (
+                update_target_block,
+                (
+                    break_block.run_if(in_game_mode(&[GameMode::Creative])),
+                    mine_block.run_if(in_game_mode(&[GameMode::Survival])),
+                    place_block.run_if(in_game_mode(&[GameMode::Survival, GameMode::Creative])),
+                    pick_block,
+                    scroll_selected_block
+                ).run_if(cursor_grabbed)
+            ).chain().run_if(resource_exists::<BlockRegistry>));
+    }
+}
+
+/// Clicks only edit blocks while the cursor is grabbed by the game
+fn cursor_grabbed(
+    window_query: Query<&Window, With<PrimaryWindow>>
+) -> bool {
+    window_query.get_single()
+        .is_ok_and(|window| window.cursor.grab_mode != CursorGrabMode::None)
+}
+
+/// Blocks the crosshair stops at, liquids are aimed through
+fn is_targetable(registry: &BlockRegistry, block: Block) -> bool {
+    registry.get(block)
+        .is_some_and(|def| def.visibility != Visibility::Empty && !def.is_liquid)
+}
+
+/// Blocks that are replaced when placing into them
+fn is_replaceable(registry: &BlockRegistry, block: Block) -> bool {
+    registry.get(block)
+        .is_none_or(|def| def.visibility == Visibility::Empty || def.is_liquid)
+}
+
+fn update_target_block(
+    mut target: ResMut<TargetBlock>,
+    camera_query: Query<&GlobalTransform, With<PlayerCamera>>,
+    world: VoxelWorld,
+    registry: Res<BlockRegistry>
+) {
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+    let hit = raycast(camera.translation(), camera.forward(), REACH, |pos| {
+        world.get_block(pos)
+            .is_some_and(|block| is_targetable(&registry, block))
+    });
+    if target.0 != hit {
+        target.0 = hit;
+    }
+}
+
+fn break_block(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    keybinds: Res<KeyBindsResource>,
+    target: Res<TargetBlock>,
+    mut world: VoxelWorld
+) {
+    if !mouse_input.just_pressed(keybinds.break_block) {
+        return;
+    }
+    if let Some(hit) = target.0 {
+        world.set_block(hit.position, Block::AIR);
+    }
+}
+
+/// Breaks the targeted block once it was held for its hardness
+fn mine_block(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    keybinds: Res<KeyBindsResource>,
+    target: Res<TargetBlock>,
+    mut progress: ResMut<BreakProgress>,
+    mut world: VoxelWorld,
+    registry: Res<BlockRegistry>,
+    time: Res<Time>
+) {
+    let Some(hit) = target.0.filter(|_| mouse_input.pressed(keybinds.break_block)) else {
+        if progress.position.is_some() {
+            *progress = BreakProgress::default();
+        }
+        return;
+    };
+    if progress.position != Some(hit.position) {
+        *progress = BreakProgress { position: Some(hit.position), elapsed: 0.0 };
+    }
+    progress.elapsed += time.delta_seconds();
+
+    let hardness = world.get_block(hit.position)
+        .and_then(|block| registry.get(block))
+        .map_or(0.0, |def| def.hardness);
+    if progress.elapsed >= hardness {
+        world.set_block(hit.position, Block::AIR);
+        *progress = BreakProgress::default();
+    }
+}
+
+fn place_block(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    keybinds: Res<KeyBindsResource>,
+    target: Res<TargetBlock>,
+    selected: Res<SelectedBlock>,
+    mut world: VoxelWorld,
+    registry: Res<BlockRegistry>,
+    player_query: Query<(&Transform, &Collider), With<Player>>
+) {
+    if !mouse_input.just_pressed(keybinds.place_block) {
+        return;
+    }
+    let Some(hit) = target.0 else {
+        return;
+    };
+    let block = Block(selected.0);
+    let pos = hit.adjacent();
+    let free = world.get_block(pos)
+        .is_some_and(|current| is_replaceable(&registry, current));
+    // Solid blocks must not trap the player
+    let blocks_player = registry.get(block).is_some_and(|def| def.is_solid)
+        && player_query.iter().any(|(transform, collider)| {
+            collider.intersects_voxel(transform.translation, pos)
+        });
+    if free && !blocks_player && registry.get(block).is_some() {
+        world.set_block(pos, block);
+    }
+}
+
+/// Selects the targeted block
+fn pick_block(
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    keybinds: Res<KeyBindsResource>,
+    target: Res<TargetBlock>,
+    mut selected: ResMut<SelectedBlock>,
+    world: VoxelWorld
+) {
+    if !mouse_input.just_pressed(keybinds.pick_block) {
+        return;
+    }
+    let block = target.0.and_then(|hit| world.get_block(hit.position));
+    if let Some(block) = block {
+        selected.0 = block.0;
+    }
+}
+
+/// Mouse wheel cycles through every block that can be placed
+fn scroll_selected_block(
+    mut wheel_events: EventReader<MouseWheel>,
+    mut selected: ResMut<SelectedBlock>,
+    registry: Res<BlockRegistry>
+) {
+    let scroll: f32 = wheel_events.read().map(|event| event.y).sum();
+    if scroll == 0.0 {
+        return;
+    }
+    let blocks: Vec<Block> = registry.iter()
+        .filter(|(_, def)| def.visibility != Visibility::Empty)
+        .map(|(block, _)| block)
+        .collect();
+    if blocks.is_empty() {
+        return;
+    }
+    let current = blocks.iter().position(|block| block.0 == selected.0).unwrap_or(0);
+    let next = if scroll > 0.0 {
+        (current + 1) % blocks.len()
+    } else {
+        (current + blocks.len() - 1) % blocks.len()
+    };
+    selected.0 = blocks[next].0;
+    if let Some(def) = registry.get(blocks[next]) {
+        info!("selected {}", def.name);
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use bevy::utils::hashbrown::HashMap;
+use super::{Chunk, MyChunk};
+use super::block::Block;
+use super::face::Side;
+use super::loader::WORLD_HEIGHT;
+use super::registry::BlockRegistry;
+use super::voxel_world::split_position;
+
+/// Light level of the open sky and of the brightest blocks
+pub const MAX_LIGHT: u8 = 15;
+
+/// Sky and block light levels of a voxel, packed in one byte
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Light(u8);
+
+impl Light {
+    /// Full sky light and no block light
+    pub const SKY: Self = Self(MAX_LIGHT << 4);
+
+    pub fn new(sky: u8, block: u8) -> Self {
+        Self(sky.min(MAX_LIGHT) << 4 | block.min(MAX_LIGHT))
+    }
+
+    pub fn sky(self) -> u8 {
+        self.0 >> 4
+    }
+
+    pub fn block(self) -> u8 {
+        self.0 & 0xF
+    }
+
+    fn get(self, channel: Channel) -> u8 {
+        match channel {
+            Channel::Sky => self.sky(),
+            Channel::Block => self.block(),
+        }
+    }
+
+    fn with(self, channel: Channel, level: u8) -> Self {
+        match channel {
+            Channel::Sky => Self::new(level, self.block()),
+            Channel::Block => Self::new(self.sky(), level),
+        }
+    }
+}
+
+/// Both kinds of light spread the same way,
+/// except that full sky light goes down without getting dimmer
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Channel {
+    Sky,
+    Block,
+}
+
+impl Channel {
+    const ALL: [Self; 2] = [Self::Sky, Self::Block];
+
+    /// Level of a neighbor lit from a voxel with `level`
+    fn spread(self, level: u8, side: Side) -> u8 {
+        if self == Self::Sky && side == Side::Bottom && level == MAX_LIGHT {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+/// World positions of blocks that were changed, their light is updated next frame
+#[derive(Resource, Default)]
+pub struct LightUpdates(pub Vec<IVec3>);
+
+/// Flood fills light over loaded chunks.
+/// Unloaded chunks stop light, and pull it in from neighbors once they load
+struct Lighting<'r, 'a> {
+    registry: &'r BlockRegistry,
+    chunks: HashMap<IVec3, Mut<'a, MyChunk>>,
+}
+
+impl<'r, 'a> Lighting<'r, 'a> {
+    fn get(&self, pos: IVec3) -> Option<(Block, Light)> {
+        let (chunk_pos, index) = split_position(pos);
+        let chunk = self.chunks.get(&chunk_pos)?;
+        Some((chunk.voxels.get(index), chunk.light[index]))
+    }
+
+    fn level(&self, pos: IVec3, channel: Channel) -> u8 {
+        self.get(pos).map_or(0, |(_, light)| light.get(channel))
+    }
+
+    /// Only writes real changes, so untouched chunks are not remeshed
+    fn set_level(&mut self, pos: IVec3, channel: Channel, level: u8) {
+        let (chunk_pos, index) = split_position(pos);
+        if let Some(chunk) = self.chunks.get_mut(&chunk_pos) {
+            let light = chunk.light[index];
+            if light.get(channel) != level {
+                chunk.light[index] = light.with(channel, level);
+            }
+        }
+    }
+
+    fn is_translucent(&self, block: Block) -> bool {
+        self.registry.get(block).is_some_and(|def| def.is_translucent)
+    }
+
+    fn emission(&self, block: Block) -> u8 {
+        self.registry.get(block).map_or(0, |def| def.light_emission.min(MAX_LIGHT))
+    }
+
+    /// Spreads light outwards from every queued position
+    fn spread(&mut self, channel: Channel, mut queue: VecDeque<IVec3>) {
+        while let Some(pos) = queue.pop_front() {
+            let level = self.level(pos, channel);
+            if level <= 1 {
+                continue;
+            }
+            for side in Side::ALL {
+                let neighbor = pos + side.offset();
+                let Some((block, light)) = self.get(neighbor) else {
+                    continue;
+                };
+                let spread = channel.spread(level, side);
+                if self.is_translucent(block) && light.get(channel) < spread {
+                    self.set_level(neighbor, channel, spread);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    /// Darkens voxels lit from the removed positions, which were already set to 0,
+    /// returns positions lit from elsewhere that have to spread again
+    fn remove(&mut self, channel: Channel, mut queue: VecDeque<(IVec3, u8)>) -> VecDeque<IVec3> {
+        let mut respread = VecDeque::new();
+        while let Some((pos, level)) = queue.pop_front() {
+            for side in Side::ALL {
+                let neighbor = pos + side.offset();
+                let Some((block, light)) = self.get(neighbor) else {
+                    continue;
+                };
+                let neighbor_level = light.get(channel);
+                if neighbor_level == 0 {
+                    continue;
+                }
+                if neighbor_level < level || channel.spread(level, side) == MAX_LIGHT {
+                    self.set_level(neighbor, channel, 0);
+                    queue.push_back((neighbor, neighbor_level));
+                    let emission = self.emission(block);
+                    if channel == Channel::Block && emission > 0 {
+                        self.set_level(neighbor, channel, emission);
+                        respread.push_back(neighbor);
+                    }
+                } else {
+                    respread.push_back(neighbor);
+                }
+            }
+        }
+        respread
+    }
+
+    /// Lights a chunk that was just loaded, together with its neighbors
+    fn light_chunk(&mut self, position: IVec3) {
+        let side = MyChunk::SIDE as i32;
+        let origin = position * side;
+        let mut sky = VecDeque::new();
+        let mut block = VecDeque::new();
+
+        // Sky light enters the world from the top of the highest chunks
+        if position.y == WORLD_HEIGHT - 1 {
+            for x in 0..side {
+                for z in 0..side {
+                    for y in (0..side).rev() {
+                        let pos = origin + IVec3::new(x, y, z);
+                        match self.get(pos) {
+                            Some((voxel, _)) if self.is_translucent(voxel) => {
+                                self.set_level(pos, Channel::Sky, MAX_LIGHT);
+                                sky.push_back(pos);
+                            },
+                            _ => break,
+                        }
+                    }
+                }
+            }
+        }
+
+        let emitters: Vec<(IVec3, u8)> = self.chunks.get(&position)
+            .map(|chunk| chunk.iter_non_empty()
+                .map(|(pos, voxel)| (origin + pos, self.emission(voxel)))
+                .filter(|(_, emission)| *emission > 0)
+                .collect())
+            .unwrap_or_default();
+        for (pos, emission) in emitters {
+            self.set_level(pos, Channel::Block, emission);
+            block.push_back(pos);
+        }
+
+        // Light of loaded neighbors flows in through the shared borders
+        for border_side in Side::ALL {
+            let axis = border_side.axis();
+            let (u_axis, v_axis) = border_side.uv_axes();
+            for u in 0..side {
+                for v in 0..side {
+                    let mut pos = origin;
+                    pos[axis] += if border_side.offset()[axis] > 0 { side } else { -1 };
+                    pos[u_axis] += u;
+                    pos[v_axis] += v;
+                    if let Some((_, light)) = self.get(pos) {
+                        if light.sky() > 0 {
+                            sky.push_back(pos);
+                        }
+                        if light.block() > 0 {
+                            block.push_back(pos);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.spread(Channel::Sky, sky);
+        self.spread(Channel::Block, block);
+    }
+
+    /// Updates light around a block that was replaced
+    fn update_block(&mut self, pos: IVec3) {
+        let Some((voxel, light)) = self.get(pos) else {
+            return;
+        };
+        let translucent = self.is_translucent(voxel);
+        for channel in Channel::ALL {
+            self.set_level(pos, channel, 0);
+            let mut respread = self.remove(channel, VecDeque::from([(pos, light.get(channel))]));
+
+            if channel == Channel::Block && self.emission(voxel) > 0 {
+                self.set_level(pos, channel, self.emission(voxel));
+                respread.push_back(pos);
+            }
+            if translucent {
+                // Neighbors light up the new opening
+                respread.extend(Side::ALL.map(|side| pos + side.offset()));
+                let top = WORLD_HEIGHT * MyChunk::SIDE as i32 - 1;
+                if channel == Channel::Sky && pos.y == top {
+                    self.set_level(pos, channel, MAX_LIGHT);
+                    respread.push_back(pos);
+                }
+            }
+            self.spread(channel, respread);
+        }
+    }
+}
+
+type AddedChunks<'w, 's> = Query<'w, 's, &'static MyChunk, Added<MyChunk>>;
+
+/// Lights new chunks and chunks around changed blocks.
+/// Chunks whose light changes are remeshed
+pub fn update_light(
+    mut chunks_query: ParamSet<(AddedChunks, Query<&mut MyChunk>)>,
+    mut updates: ResMut<LightUpdates>,
+    registry: Res<BlockRegistry>
+) {
+    let added: Vec<IVec3> = chunks_query.p0().iter().map(|chunk| chunk.position).collect();
+    if added.is_empty() && updates.0.is_empty() {
+        return;
+    }
+
+    let mut chunks_query = chunks_query.p1();
+    let chunks = chunks_query.iter_mut()
+        .map(|chunk| (chunk.position, chunk))
+        .collect();
+    let mut lighting = Lighting { registry: &registry, chunks };
+    for position in added {
+        lighting.light_chunk(position);
+    }
+    for pos in updates.0.drain(..) {
+        lighting.update_block(pos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::ChunkMut;
+    use super::super::registry::BlockRegistryAsset;
+
+    const GLOWSTONE: Block = Block(11);
+    const TOP: i32 = WORLD_HEIGHT - 1;
+
+    fn registry() -> BlockRegistry {
+        let asset: BlockRegistryAsset = ron::de::from_str("(blocks: [
+            (id: 0, name: \"air\", visibility: Empty, is_translucent: true),
+            (id: 2, name: \"stone\"),
+            (id: 11, name: \"glowstone\", light_emission: 15),
+        ])").unwrap();
+        BlockRegistry::new(&asset.blocks)
+    }
+
+    fn chunk(position: IVec3, blocks: &[(IVec3, Block)]) -> MyChunk {
+        let mut chunk = MyChunk::default().with_position(position);
+        for (pos, block) in blocks {
+            chunk.set(*pos, *block);
+        }
+        chunk
+    }
+
+    /// Runs `f` over every chunk in `world`
+    fn with_lighting(world: &mut World, f: impl FnOnce(&mut Lighting)) {
+        let registry = registry();
+        let mut query = world.query::<&mut MyChunk>();
+        let chunks = query.iter_mut(world)
+            .map(|chunk| (chunk.position, chunk))
+            .collect();
+        f(&mut Lighting { registry: &registry, chunks });
+    }
+
+    /// Loads a chunk and lights it
+    fn load(world: &mut World, chunk: MyChunk) {
+        let position = chunk.position;
+        world.spawn(chunk);
+        with_lighting(world, |lighting| lighting.light_chunk(position));
+    }
+
+    fn light(world: &mut World, pos: IVec3) -> Light {
+        let (chunk_pos, index) = split_position(pos);
+        let mut query = world.query::<&MyChunk>();
+        query.iter(world)
+            .find(|chunk| chunk.position == chunk_pos)
+            .map_or(Light::default(), |chunk| chunk.light[index])
+    }
+
+    fn top(x: i32, y: i32, z: i32) -> IVec3 {
+        IVec3::new(x, TOP * MyChunk::SIDE as i32 + y, z)
+    }
+
+    #[test]
+    fn sky_light_goes_down_and_fades_sideways() {
+        // Stone roof with a hole in the middle
+        let roof: Vec<(IVec3, Block)> = (0..16)
+            .flat_map(|x| (0..16).map(move |z| IVec3::new(x, 15, z)))
+            .filter(|pos| *pos != IVec3::new(8, 15, 8))
+            .map(|pos| (pos, Block::STONE))
+            .collect();
+        let mut world = World::new();
+        load(&mut world, chunk(IVec3::new(0, TOP, 0), &roof));
+
+        for y in 0..16 {
+            assert_eq!(light(&mut world, top(8, y, 8)).sky(), MAX_LIGHT, "hole at y {y}");
+        }
+        assert_eq!(light(&mut world, top(8, 15, 9)).sky(), 0);
+        assert_eq!(light(&mut world, top(9, 5, 8)).sky(), 14);
+        assert_eq!(light(&mut world, top(10, 5, 8)).sky(), 13);
+        assert_eq!(light(&mut world, top(10, 5, 9)).sky(), 12);
+        assert_eq!(light(&mut world, top(8, 5, 8)).block(), 0);
+    }
+
+    #[test]
+    fn block_light_fades_with_distance() {
+        let mut world = World::new();
+        load(&mut world, chunk(IVec3::ZERO, &[(IVec3::splat(8), GLOWSTONE)]));
+
+        assert_eq!(light(&mut world, IVec3::splat(8)).block(), 15);
+        assert_eq!(light(&mut world, IVec3::new(9, 8, 8)).block(), 14);
+        assert_eq!(light(&mut world, IVec3::new(8, 8, 11)).block(), 12);
+        assert_eq!(light(&mut world, IVec3::new(10, 9, 7)).block(), 11);
+        assert_eq!(light(&mut world, IVec3::new(15, 15, 15)).block(), 0);
+        // Chunk is below the top layer, so no sky reaches it
+        assert_eq!(light(&mut world, IVec3::new(9, 8, 8)).sky(), 0);
+    }
+
+    #[test]
+    fn placed_blocks_remove_light() {
+        let mut world = World::new();
+        load(&mut world, chunk(IVec3::new(0, TOP, 0), &[]));
+        load(&mut world, chunk(IVec3::ZERO, &[(IVec3::splat(8), GLOWSTONE)]));
+        assert_eq!(light(&mut world, top(8, 0, 8)).sky(), MAX_LIGHT);
+
+        // Roof over one column
+        let mut query = world.query::<&mut MyChunk>();
+        for mut chunk in query.iter_mut(&mut world) {
+            if chunk.position.y == TOP {
+                chunk.set(IVec3::new(8, 15, 8), Block::STONE);
+            } else {
+                chunk.set(IVec3::splat(8), Block::STONE);
+            }
+        }
+        with_lighting(&mut world, |lighting| {
+            lighting.update_block(top(8, 15, 8));
+            lighting.update_block(IVec3::splat(8));
+        });
+
+        assert_eq!(light(&mut world, top(8, 15, 8)).sky(), 0);
+        assert_eq!(light(&mut world, top(8, 14, 8)).sky(), 14);
+        assert_eq!(light(&mut world, top(8, 0, 8)).sky(), 14);
+        assert_eq!(light(&mut world, top(9, 0, 8)).sky(), MAX_LIGHT);
+        for pos in [IVec3::splat(8), IVec3::new(9, 8, 8), IVec3::new(8, 8, 11)] {
+            assert_eq!(light(&mut world, pos).block(), 0, "block light at {pos}");
+        }
+    }
+
+    #[test]
+    fn light_crosses_into_chunks_loaded_later() {
+        let mut world = World::new();
+        load(&mut world, chunk(IVec3::ZERO, &[(IVec3::new(15, 8, 8), GLOWSTONE)]));
+        assert_eq!(light(&mut world, IVec3::new(16, 8, 8)).block(), 0);
+
+        load(&mut world, chunk(IVec3::X, &[]));
+        assert_eq!(light(&mut world, IVec3::new(15, 8, 8)).block(), 15);
+        assert_eq!(light(&mut world, IVec3::new(16, 8, 8)).block(), 14);
+        assert_eq!(light(&mut world, IVec3::new(18, 9, 8)).block(), 11);
+    }
+}
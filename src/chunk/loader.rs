@@ -1,43 +1,149 @@
 use bevy::prelude::*;
-use bevy::utils::hashbrown::HashMap;
-use crate::player::Player;
+use bevy::app::AppExit;
+use bevy::tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, IoTaskPool, Task};
+use bevy::render::mesh::Indices;
+use bevy::render::primitives::Aabb;
+use bevy::utils::hashbrown::{HashMap, HashSet};
+use crate::player::{Player, PlayerCamera};
 
-use super::MyChunk;
-use super::simple_mesh;
+use super::{Chunk, MyChunk, Mesher};
+use super::mesh::{build_mesh, TransparentFaces};
+use super::face::{Face, Side};
+use super::view::ChunkView;
+use super::material::{ChunkMaterial, ChunkMaterials, ChunkMaterialPlugin};
+use super::registry::{BlockInfos, BlockRegistry, BlockRegistryPlugin};
+use super::terrain::{TerrainPlugin, WorldGenerator};
+use super::interaction::BlockInteractionPlugin;
+use super::debug::ChunkDebugPlugin;
+use super::light::{update_light, LightUpdates};
+use super::region::ChunkStorage;
 
 const RENDER_DISTANCE: usize = 12;
+/// Number of chunk layers stacked vertically, starting at y = 0
+pub const WORLD_HEIGHT: i32 = 4;
+/// How many finished chunk meshes are attached per frame
+const MESHES_PER_FRAME: usize = 8;
+/// How far the camera moves before transparent faces are sorted again
+const SORT_DISTANCE: f32 = 2.0;
 
 #[derive(Resource, PartialEq)]
-struct CurrentChunk(IVec3);
+pub struct CurrentChunk(pub IVec3);
 
 #[derive(Resource)]
-struct ChunkMap {
-    chunks: HashMap<IVec3, Entity>
+pub struct ChunkMap {
+    pub chunks: HashMap<IVec3, Entity>
 }
 
 impl Default for ChunkMap {
     fn default() -> Self {
-        Self { chunks: HashMap::with_capacity((RENDER_DISTANCE * RENDER_DISTANCE) as usize) }
+        Self { chunks: HashMap::with_capacity(RENDER_DISTANCE * RENDER_DISTANCE) }
     }
 }
 
-#[derive(Component, Default)]
-enum ChunkState {
+/// Lifecycle of a chunk entity:
+/// `Loading -> Loaded <-> Refreshing`, then `Unloading` before despawn
+#[derive(Component, Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[reflect(Component)]
+pub enum ChunkState {
+    /// Voxels are being generated, or the chunk has no mesh yet
     #[default]
     Loading,
+    /// Out of render distance, will be despawned
     Unloading,
+    /// Voxels have changed since the mesh was built
     Refreshing,
+    /// Mesh is up to date with voxels
     Loaded,
 }
 
+/// Voxels differ from what the generator produces,
+/// so the chunk is saved when unloaded or when the app exits
+#[derive(Component)]
+pub struct ChunkModified;
+
+/// Voxels being loaded or generated in background,
+/// [`MyChunk`] is inserted when done
+#[derive(Component)]
+struct ChunkGenTask(Task<MyChunk>);
+
+/// Result of [`ChunkMeshTask`], parts without faces are `None`
+struct ChunkMeshData {
+    opaque: Option<Mesh>,
+    transparent: Option<(Mesh, TransparentFaces)>,
+}
+
+/// Mesh being built in background for a chunk
+/// in [`ChunkState::Loading`] or [`ChunkState::Refreshing`]
+#[derive(Component)]
+struct ChunkMeshTask(Task<ChunkMeshData>);
+
+/// Child entities of a chunk drawing its opaque and transparent faces,
+/// spawned with the first mesh
+#[derive(Component, Clone, Copy)]
+struct ChunkSubMeshes {
+    opaque: Entity,
+    transparent: Entity,
+}
+
+/// Sent when a chunk gets its first mesh
+#[derive(Event)]
+pub struct ChunkLoaded(pub IVec3);
+
+/// Sent when a chunk mesh is rebuilt after its voxels changed
+#[derive(Event)]
+pub struct ChunkRefreshed(pub IVec3);
+
+/// Sent when a chunk is despawned
+#[derive(Event)]
+pub struct ChunkUnloaded(pub IVec3);
+
 pub struct ChunkPlugin;
 
 impl Plugin for ChunkPlugin {
     fn build(&self, app: &mut App) {
         app
+            .add_plugins((
+                BlockRegistryPlugin,
+                ChunkMaterialPlugin,
+                TerrainPlugin,
+                BlockInteractionPlugin,
+                ChunkDebugPlugin
+            ))
             .insert_resource(CurrentChunk(IVec3::ZERO))
             .insert_resource(ChunkMap::default())
-            .add_systems(Update, update_current_chunk);
+            .init_resource::<LightUpdates>()
+            .init_resource::<Mesher>()
+            .register_type::<ChunkState>()
+            .register_type::<Mesher>()
+            .add_event::<ChunkLoaded>()
+            .add_event::<ChunkRefreshed>()
+            .add_event::<ChunkUnloaded>()
+            .add_systems(Update, (
+                update_current_chunk,
+                reload_chunks.run_if(resource_exists_and_changed::<WorldGenerator>),
+                load_chunks.run_if(
+                    resource_exists::<WorldGenerator>
+                        .and_then(resource_exists::<ChunkStorage>)
+                        .and_then(resource_changed::<CurrentChunk>)
+                ),
+                finish_chunk_generation,
+                update_light.run_if(resource_exists::<BlockRegistry>),
+                refresh_chunks,
+                remesh_chunks.run_if(
+                    resource_changed::<Mesher>
+                        .or_else(resource_exists_and_changed::<BlockInfos>)
+                ),
+                queue_chunk_meshes.run_if(
+                    resource_exists::<BlockInfos>
+                        .and_then(resource_exists::<WorldGenerator>)
+                ),
+                render_chunks.run_if(resource_exists::<ChunkMaterials>),
+                sort_transparent_faces,
+                unload_chunks.run_if(resource_exists::<ChunkStorage>)
+            ).chain())
+            .add_systems(Last, save_chunks_on_exit.run_if(
+                resource_exists::<ChunkStorage>.and_then(on_event::<AppExit>())
+            ));
     }
 }
 
@@ -47,82 +153,739 @@ fn update_current_chunk(
 ) {
     if let Ok(player) = player_query.get_single() {
         let pos = player.translation;
-        let chunk_pos = (pos / 16.0).floor().as_ivec3();
+        let chunk_pos = (pos / MyChunk::SIDE as f32).floor().as_ivec3();
         if current_chunk.0 != chunk_pos {
             *current_chunk = CurrentChunk(chunk_pos);
         }
     }
 }
 
-// fn new_chunk(pos: IVec3) -> MyChunk {
-//     MyChunk::flat(5)
-//         .with_position(pos)
-// }
-
-// fn render_chunks(
-//     chunks_query: Query<(Entity, &mut MyChunk, &ChunkState)>,
-//     chunk_map: Res<ChunkMap>
-// ) {
-//     //
-// }
-
-// fn render_chunk(
-//     mut commands: Commands,
-//     mut meshes: ResMut<Assets<Mesh>>,
-//     mut materials: ResMut<Assets<StandardMaterial>>,
-//     asset_server: Res<AssetServer>
-// ) {
-//     let chunk = MyChunk::flat(5);
-
-//     let result = simple_mesh(&chunk);
-
-//     let mut positions = Vec::new();
-//     let mut indices = Vec::new();
-//     let mut normals = Vec::new();
-//     let mut uvs = Vec::new();
-
-//     for face in result.iter() {
-//         indices.extend_from_slice(&face.indices(positions.len() as u32));
-//         positions.extend_from_slice(&face.positions(1.0));
-//         normals.extend_from_slice(&face.normals());
-//         uvs.extend_from_slice(&face.uvs());
-//     }
-
-//     let mesh = Mesh::new(
-//         PrimitiveTopology::TriangleList,
-//         RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD
-//     )
-//         .with_inserted_attribute(
-//             Mesh::ATTRIBUTE_POSITION,
-//             VertexAttributeValues::Float32x3(positions)
-//         )
-//         .with_inserted_indices(
-//             Indices::U32(indices)
-//         )
-//         .with_inserted_attribute(
-//             Mesh::ATTRIBUTE_UV_0,
-//             VertexAttributeValues::Float32x2(uvs)
-//         )
-//         .with_inserted_attribute(
-//             Mesh::ATTRIBUTE_NORMAL,
-//             VertexAttributeValues::Float32x3(normals)
-//         );
-    
-//     let mesh_handle = meshes.add(mesh);
-//     let texture = asset_server.load("dirt.png");
-
-//     commands.spawn((
-//         PbrBundle {
-//             mesh: mesh_handle,
-//             material: materials.add(StandardMaterial {
-//                 base_color_texture: Some(texture),
-//                 unlit: true, // remove later
-//                 // cull_mode: None,
-//                 ..default()
-//             }),
-//             transform: Transform::from_xyz(0.0, 0.0, 0.0),
-//             ..default()
-//         },
-//         Name::new("BIG CHUNK (of your memory)")
-//     ));
-// }
\ No newline at end of file
+/// Positions of every chunk that should be loaded around `center`
+fn chunks_in_range(center: IVec3) -> HashSet<IVec3> {
+    let radius = RENDER_DISTANCE as i32;
+    let mut positions = HashSet::with_capacity((radius * radius * 4) as usize);
+    for x in -radius..=radius {
+        for z in -radius..=radius {
+            if x * x + z * z > radius * radius {
+                continue;
+            }
+            for y in 0..WORLD_HEIGHT {
+                positions.insert(IVec3::new(center.x + x, y, center.z + z));
+            }
+        }
+    }
+    positions
+}
+
+/// Chunks still being generated are dropped right away,
+/// loaded ones go through [`ChunkState::Unloading`]
+/// and their mesh in progress is dropped
+fn unload_chunk(
+    commands: &mut Commands,
+    entity: Entity,
+    states_query: &mut Query<(&mut ChunkState, Has<MyChunk>)>
+) {
+    match states_query.get_mut(entity) {
+        Ok((mut state, true)) => {
+            *state = ChunkState::Unloading;
+            commands.entity(entity).remove::<ChunkMeshTask>();
+        },
+        _ => commands.entity(entity).despawn_recursive(),
+    }
+}
+
+fn load_chunks(
+    mut commands: Commands,
+    current_chunk: Res<CurrentChunk>,
+    mut chunk_map: ResMut<ChunkMap>,
+    mut states_query: Query<(&mut ChunkState, Has<MyChunk>)>,
+    generator: Res<WorldGenerator>,
+    storage: Res<ChunkStorage>
+) {
+    let in_range = chunks_in_range(current_chunk.0);
+
+    chunk_map.chunks.retain(|pos, entity| {
+        let keep = in_range.contains(pos);
+        if !keep {
+            unload_chunk(&mut commands, *entity, &mut states_query);
+        }
+        keep
+    });
+
+    // Chunks closest to the player are generated first
+    let mut missing: Vec<IVec3> = in_range.into_iter()
+        .filter(|pos| !chunk_map.chunks.contains_key(pos))
+        .collect();
+    missing.sort_by_key(|pos| pos.distance_squared(current_chunk.0));
+
+    let pool = AsyncComputeTaskPool::get();
+    for pos in missing {
+        let generator = generator.0.clone();
+        let storage = storage.0.clone();
+        let task = pool.spawn(async move {
+            let saved = storage.load(pos).unwrap_or_else(|error| {
+                error!("Failed to load chunk at {pos}: {error}");
+                None
+            });
+            if let Some(chunk) = saved {
+                return chunk;
+            }
+            let mut chunk = generator.generate(pos);
+            generator.decorate(&mut chunk);
+            chunk
+        });
+        let entity = commands.spawn((
+            SpatialBundle::from_transform(
+                Transform::from_translation(pos.as_vec3() * MyChunk::SIDE as f32)
+            ),
+            ChunkGenTask(task),
+            ChunkState::Loading,
+            Name::new(format!("Chunk at {}, {}, {}", pos.x, pos.y, pos.z))
+        )).id();
+        chunk_map.chunks.insert(pos, entity);
+    }
+}
+
+/// Drops every chunk so the world is generated again around the player
+fn reload_chunks(
+    mut commands: Commands,
+    mut current_chunk: ResMut<CurrentChunk>,
+    mut chunk_map: ResMut<ChunkMap>,
+    mut states_query: Query<(&mut ChunkState, Has<MyChunk>)>
+) {
+    for (_, entity) in chunk_map.chunks.drain() {
+        unload_chunk(&mut commands, entity, &mut states_query);
+    }
+    current_chunk.set_changed();
+}
+
+fn finish_chunk_generation(
+    mut commands: Commands,
+    mut tasks_query: Query<(Entity, &mut ChunkGenTask)>
+) {
+    for (entity, mut task) in tasks_query.iter_mut() {
+        if let Some(chunk) = block_on(future::poll_once(&mut task.0)) {
+            commands.entity(entity)
+                .insert(chunk)
+                .remove::<ChunkGenTask>();
+        }
+    }
+}
+
+/// Makes sure the chunk mesh gets rebuilt from its current voxels
+fn invalidate_mesh(
+    commands: &mut Commands,
+    entity: Entity,
+    state: &mut ChunkState,
+    has_task: bool
+) {
+    match *state {
+        ChunkState::Loaded => {
+            *state = ChunkState::Refreshing;
+        },
+        // Mesh in progress was built from outdated voxels
+        ChunkState::Loading | ChunkState::Refreshing if has_task => {
+            commands.entity(entity).remove::<ChunkMeshTask>();
+        },
+        _ => ()
+    }
+}
+
+/// Rebuilds meshes of chunks whose voxels changed, and of their neighbors,
+/// because faces on the shared border depend on both.
+/// Neighbors across edges and corners are included for ambient occlusion
+fn refresh_chunks(
+    mut commands: Commands,
+    changed_query: Query<&MyChunk, Changed<MyChunk>>,
+    mut states_query: Query<(&mut ChunkState, Has<ChunkMeshTask>)>,
+    chunk_map: Res<ChunkMap>
+) {
+    for chunk in changed_query.iter() {
+        let positions = (0..27).map(|i| chunk.position + IVec3::new(i % 3, i / 3 % 3, i / 9) - 1);
+        for pos in positions {
+            let Some(&entity) = chunk_map.chunks.get(&pos) else {
+                continue;
+            };
+            if let Ok((mut state, has_task)) = states_query.get_mut(entity) {
+                invalidate_mesh(&mut commands, entity, &mut state, has_task);
+            }
+        }
+    }
+}
+
+/// Rebuilds every chunk mesh, e.g. after [`Mesher`] was switched
+/// or blocks were reloaded
+fn remesh_chunks(
+    mut commands: Commands,
+    mut chunks_query: Query<(Entity, &mut ChunkState, Has<ChunkMeshTask>)>
+) {
+    for (entity, mut state, has_task) in chunks_query.iter_mut() {
+        invalidate_mesh(&mut commands, entity, &mut state, has_task);
+    }
+}
+
+fn queue_chunk_meshes(
+    mut commands: Commands,
+    chunks_query: Query<(Entity, &MyChunk, &ChunkState), Without<ChunkMeshTask>>,
+    neighbors_query: Query<&MyChunk>,
+    chunk_map: Res<ChunkMap>,
+    mesher: Res<Mesher>,
+    infos: Res<BlockInfos>,
+    generator: Res<WorldGenerator>
+) {
+    let pool = AsyncComputeTaskPool::get();
+    for (entity, chunk, state) in chunks_query.iter() {
+        if !matches!(state, ChunkState::Loading | ChunkState::Refreshing) {
+            continue;
+        }
+        let neighbors = std::array::from_fn(|i| {
+            let pos = chunk.position + Side::from(i).offset();
+            chunk_map.chunks.get(&pos)
+                .and_then(|entity| neighbors_query.get(*entity).ok())
+                .cloned()
+        });
+        let diagonal = |offset: IVec3| {
+            chunk_map.chunks.get(&(chunk.position + offset))
+                .and_then(|entity| neighbors_query.get(*entity).ok())
+        };
+        // Boxed so the task future stays small, seven chunks overflow the stack
+        // of system threads while being moved around in debug builds
+        let view = Box::new(
+            ChunkView::new(chunk.clone(), neighbors, infos.clone()).with_diagonals(diagonal)
+        );
+        let mesher = *mesher;
+        let generator = generator.0.clone();
+        let origin = chunk.position * MyChunk::SIDE as i32;
+        let task = pool.spawn(async move {
+            let tint = |pos: UVec3| {
+                let pos = origin + pos.as_ivec3();
+                generator.biome(pos.x, pos.z).tint()
+            };
+            let (transparent, opaque): (Vec<Face>, Vec<Face>) = mesher.mesh(&*view, tint)
+                .into_iter()
+                .partition(|face| face.transparent);
+            ChunkMeshData {
+                opaque: (!opaque.is_empty()).then(|| build_mesh(&opaque, tint)),
+                transparent: (!transparent.is_empty()).then(|| {
+                    (build_mesh(&transparent, tint), TransparentFaces::new(&transparent))
+                }),
+            }
+        });
+        commands.entity(entity).insert(ChunkMeshTask(task));
+    }
+}
+
+fn render_chunks(
+    mut commands: Commands,
+    mut chunks_query: Query<(
+        Entity,
+        &MyChunk,
+        &mut ChunkState,
+        &mut ChunkMeshTask,
+        Option<&ChunkSubMeshes>
+    )>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    materials: Res<ChunkMaterials>,
+    mut loaded_events: EventWriter<ChunkLoaded>,
+    mut refreshed_events: EventWriter<ChunkRefreshed>
+) {
+    let mut applied = 0;
+    for (entity, chunk, mut state, mut task, sub_meshes) in chunks_query.iter_mut() {
+        if applied >= MESHES_PER_FRAME {
+            break;
+        }
+        let Some(data) = block_on(future::poll_once(&mut task.0)) else {
+            continue;
+        };
+        match *state {
+            ChunkState::Loading => {
+                loaded_events.send(ChunkLoaded(chunk.position));
+            },
+            ChunkState::Refreshing => {
+                refreshed_events.send(ChunkRefreshed(chunk.position));
+            },
+            // Chunk is about to be despawned, it must stay `Unloading`
+            ChunkState::Unloading => {
+                commands.entity(entity).remove::<ChunkMeshTask>();
+                continue;
+            },
+            ChunkState::Loaded => ()
+        }
+        let sub_meshes = match sub_meshes {
+            Some(sub_meshes) => *sub_meshes,
+            None => {
+                let mut spawn = |material: &Handle<ChunkMaterial>, name: &'static str| {
+                    commands.spawn((
+                        SpatialBundle::default(),
+                        material.clone(),
+                        Name::new(name)
+                    )).id()
+                };
+                let sub_meshes = ChunkSubMeshes {
+                    opaque: spawn(&materials.opaque, "Opaque mesh"),
+                    transparent: spawn(&materials.transparent, "Transparent mesh"),
+                };
+                commands.entity(entity)
+                    .insert(sub_meshes)
+                    .push_children(&[sub_meshes.opaque, sub_meshes.transparent]);
+                sub_meshes
+            }
+        };
+
+        // Bounds are only computed for entities without them,
+        // stale ones would cull the new faces
+        let mut opaque = commands.entity(sub_meshes.opaque);
+        opaque.remove::<Aabb>();
+        match data.opaque {
+            Some(mesh) => opaque.insert(meshes.add(mesh)),
+            None => opaque.remove::<Handle<Mesh>>(),
+        };
+        let mut transparent = commands.entity(sub_meshes.transparent);
+        transparent.remove::<Aabb>();
+        match data.transparent {
+            Some((mesh, faces)) => transparent.insert((meshes.add(mesh), faces)),
+            None => transparent.remove::<(Handle<Mesh>, TransparentFaces)>(),
+        };
+
+        commands.entity(entity).remove::<ChunkMeshTask>();
+        *state = ChunkState::Loaded;
+        applied += 1;
+    }
+}
+
+/// Orders transparent faces back to front for the camera,
+/// once they are built and whenever the camera moved far enough
+fn sort_transparent_faces(
+    camera_query: Query<&GlobalTransform, With<PlayerCamera>>,
+    mut faces_query: Query<(&mut TransparentFaces, &Handle<Mesh>, &Parent)>,
+    chunks_query: Query<&Transform, With<MyChunk>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut sorted_from: Local<Option<Vec3>>
+) {
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+    let eye = camera.translation();
+    let moved = sorted_from.is_none_or(|last| last.distance(eye) > SORT_DISTANCE);
+    if moved {
+        *sorted_from = Some(eye);
+    }
+
+    for (mut faces, handle, parent) in faces_query.iter_mut() {
+        if !moved && !faces.is_changed() {
+            continue;
+        }
+        // Sub-meshes sit at the origin of their chunk
+        let Ok(chunk_transform) = chunks_query.get(parent.get()) else {
+            continue;
+        };
+        let Some(mesh) = meshes.get_mut(handle) else {
+            continue;
+        };
+        let indices = faces.bypass_change_detection().sort(eye - chunk_transform.translation);
+        mesh.insert_indices(Indices::U32(indices));
+    }
+}
+
+fn unload_chunks(
+    mut commands: Commands,
+    chunks_query: Query<(Entity, &MyChunk, &ChunkState, Has<ChunkModified>)>,
+    mut unloaded_events: EventWriter<ChunkUnloaded>,
+    storage: Res<ChunkStorage>
+) {
+    let mut modified = Vec::new();
+    for (entity, chunk, state, is_modified) in chunks_query.iter() {
+        if *state == ChunkState::Unloading {
+            if is_modified {
+                modified.push(chunk);
+            }
+            commands.entity(entity).despawn_recursive();
+            unloaded_events.send(ChunkUnloaded(chunk.position));
+        }
+    }
+    if modified.is_empty() {
+        return;
+    }
+    // Chunks can be loaded again from the cache right away,
+    // region files are written in background
+    if let Err(error) = storage.0.store(modified) {
+        error!("Failed to save chunks: {error}");
+        return;
+    }
+    let storage = storage.0.clone();
+    IoTaskPool::get().spawn(async move {
+        if let Err(error) = storage.flush() {
+            error!("Failed to save chunks: {error}");
+        }
+    }).detach();
+}
+
+fn save_chunks_on_exit(
+    chunks_query: Query<&MyChunk, With<ChunkModified>>,
+    storage: Res<ChunkStorage>
+) {
+    if let Err(error) = storage.0.save(chunks_query.iter()) {
+        error!("Failed to save chunks: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use bevy::asset::AssetPlugin;
+    use bevy::input::InputPlugin;
+    use crate::keybinds::KeyBindPlugin;
+    use super::*;
+    use super::super::ChunkMut;
+    use bevy::render::view::calculate_bounds;
+    use super::super::biome::Biome;
+    use super::super::block::{Block, BlockInfo, Visibility};
+    use super::super::region::RegionStorage;
+    use super::super::terrain::{TerrainGenerator, WorldSeed};
+    use super::super::voxel_world::split_position;
+
+    /// Region directory of a test app, removed with the app
+    #[derive(Resource)]
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn test_app(name: &str) -> App {
+        let dir = std::env::temp_dir().join(format!("bevycraft-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut app = App::new();
+        app
+            .add_plugins((
+                MinimalPlugins,
+                AssetPlugin::default(),
+                InputPlugin,
+                HierarchyPlugin,
+                TransformPlugin,
+                KeyBindPlugin,
+            ))
+            .init_asset::<Mesh>()
+            .init_asset::<Image>()
+            .init_asset::<StandardMaterial>()
+            .init_asset::<Shader>()
+            .add_plugins(ChunkPlugin)
+            .insert_resource(ChunkStorage(Arc::new(RegionStorage::new(dir.clone()))))
+            .insert_resource(TempDir(dir));
+        app
+    }
+
+    fn chunk_positions(app: &App) -> HashSet<IVec3> {
+        app.world.resource::<ChunkMap>().chunks.keys().copied().collect()
+    }
+
+    fn chunk_entities(app: &App) -> Vec<Entity> {
+        app.world.resource::<ChunkMap>().chunks.values().copied().collect()
+    }
+
+    /// Runs until every chunk in range got its voxels
+    fn finish_generation(app: &mut App) {
+        for _ in 0..10_000 {
+            app.update();
+            let world = &app.world;
+            let done = world.resource::<ChunkMap>().chunks.values()
+                .all(|entity| world.get::<MyChunk>(*entity).is_some());
+            if done {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("chunks were not generated in time");
+    }
+
+    /// Lets chunks get meshed without loading textures, every block is air
+    fn enable_meshing(app: &mut App) {
+        app
+            .insert_resource(BlockInfos::new(Vec::new()))
+            .insert_resource(ChunkMaterials {
+                opaque: Handle::default(),
+                transparent: Handle::default(),
+            });
+    }
+
+    /// Runs until every chunk in range has an up to date mesh
+    fn finish_meshing(app: &mut App) {
+        for _ in 0..10_000 {
+            app.update();
+            let world = &app.world;
+            let done = world.resource::<ChunkMap>().chunks.values()
+                .all(|entity| world.get::<ChunkState>(*entity) == Some(&ChunkState::Loaded));
+            if done {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        panic!("chunks were not meshed in time");
+    }
+
+    /// Positions sent with each lifecycle event so far
+    #[derive(Resource, Default)]
+    struct ChunkEvents {
+        loaded: Vec<IVec3>,
+        refreshed: Vec<IVec3>,
+        unloaded: Vec<IVec3>,
+    }
+
+    fn record_chunk_events(
+        mut events: ResMut<ChunkEvents>,
+        mut loaded_events: EventReader<ChunkLoaded>,
+        mut refreshed_events: EventReader<ChunkRefreshed>,
+        mut unloaded_events: EventReader<ChunkUnloaded>
+    ) {
+        events.loaded.extend(loaded_events.read().map(|event| event.0));
+        events.refreshed.extend(refreshed_events.read().map(|event| event.0));
+        events.unloaded.extend(unloaded_events.read().map(|event| event.0));
+    }
+
+    #[test]
+    fn loads_chunks_in_range() {
+        let mut app = test_app("load");
+        app.update();
+        assert_eq!(chunk_positions(&app), chunks_in_range(IVec3::ZERO));
+
+        app.world.insert_resource(CurrentChunk(IVec3::new(3, 0, -2)));
+        app.update();
+        assert_eq!(chunk_positions(&app), chunks_in_range(IVec3::new(3, 0, -2)));
+    }
+
+    #[test]
+    fn spawns_closest_chunks_first() {
+        let mut app = test_app("load-order");
+        let center = IVec3::new(-4, 1, 7);
+        app.world.insert_resource(CurrentChunk(center));
+        app.update();
+
+        let mut spawned: Vec<(Entity, IVec3)> = app.world.resource::<ChunkMap>().chunks.iter()
+            .map(|(pos, entity)| (*entity, *pos))
+            .collect();
+        spawned.sort_by_key(|(entity, _)| entity.index());
+        let distances: Vec<i32> = spawned.iter()
+            .map(|(_, pos)| pos.distance_squared(center))
+            .collect();
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn despawns_generating_chunks_out_of_range() {
+        let mut app = test_app("unload-generating");
+        app.update();
+        let old = chunk_entities(&app);
+
+        let center = IVec3::new(100, 0, 100);
+        app.world.insert_resource(CurrentChunk(center));
+        app.update();
+        assert_eq!(chunk_positions(&app), chunks_in_range(center));
+        assert!(old.iter().all(|entity| app.world.get_entity(*entity).is_none()));
+    }
+
+    #[test]
+    fn despawns_loaded_chunks_out_of_range() {
+        let mut app = test_app("unload-loaded");
+        app.update();
+        finish_generation(&mut app);
+
+        let center = IVec3::new(5, 0, 0);
+        let (old_range, new_range) = (chunks_in_range(IVec3::ZERO), chunks_in_range(center));
+        let chunk_map = app.world.resource::<ChunkMap>();
+        let kept: Vec<Entity> = old_range.intersection(&new_range)
+            .map(|pos| chunk_map.chunks[pos])
+            .collect();
+        let dropped: Vec<Entity> = old_range.difference(&new_range)
+            .map(|pos| chunk_map.chunks[pos])
+            .collect();
+
+        app.world.insert_resource(CurrentChunk(center));
+        app.update();
+        assert_eq!(chunk_positions(&app), new_range);
+        assert!(kept.iter().all(|entity| app.world.get_entity(*entity).is_some()));
+        assert!(dropped.iter().all(|entity| app.world.get_entity(*entity).is_none()));
+    }
+
+    #[test]
+    fn despawns_chunks_unloaded_while_meshing() {
+        let mut app = test_app("unload-meshing");
+        app.insert_resource(BlockInfos::new(Vec::new()));
+        app.update();
+        finish_generation(&mut app);
+        // Meshes are built, but not attached before materials exist
+        app.update();
+        let center = IVec3::new(5, 0, 0);
+        let chunk_map = app.world.resource::<ChunkMap>();
+        let dropped: Vec<Entity> = chunks_in_range(IVec3::ZERO).difference(&chunks_in_range(center))
+            .map(|pos| chunk_map.chunks[pos])
+            .collect();
+        assert!(dropped.iter().all(|entity| app.world.get::<ChunkMeshTask>(*entity).is_some()));
+        // Meshes finish in the same frame their chunks are unloaded
+        for _ in 0..10_000 {
+            let finished = dropped.iter()
+                .all(|entity| app.world.get::<ChunkMeshTask>(*entity).unwrap().0.is_finished());
+            if finished {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        enable_meshing(&mut app);
+        app.world.insert_resource(CurrentChunk(center));
+        app.update();
+        app.update();
+        assert!(dropped.iter().all(|entity| app.world.get_entity(*entity).is_none()));
+    }
+
+    #[test]
+    fn sends_lifecycle_events() {
+        let mut app = test_app("events");
+        enable_meshing(&mut app);
+        app
+            .init_resource::<ChunkEvents>()
+            .add_systems(Last, record_chunk_events);
+        app.update();
+        finish_generation(&mut app);
+        finish_meshing(&mut app);
+
+        let range = chunks_in_range(IVec3::ZERO);
+        let mut events = app.world.resource_mut::<ChunkEvents>();
+        assert_eq!(events.loaded.len(), range.len());
+        assert_eq!(events.loaded.iter().copied().collect::<HashSet<_>>(), range);
+        // Light spreading into chunks loaded earlier refreshes them
+        events.refreshed.clear();
+
+        // Neighbors share faces, edges or corners with the edited chunk, so they are refreshed too
+        let edited = IVec3::new(0, 1, 0);
+        let entity = app.world.resource::<ChunkMap>().chunks[&edited];
+        app.world.get_mut::<MyChunk>(entity).unwrap().set(IVec3::ZERO, Block::STONE);
+        finish_meshing(&mut app);
+        let refreshed: HashSet<IVec3> = app.world.resource::<ChunkEvents>().refreshed
+            .iter()
+            .copied()
+            .collect();
+        let expected: HashSet<IVec3> = (0..27)
+            .map(|i| edited + IVec3::new(i % 3, i / 3 % 3, i / 9) - 1)
+            .filter(|pos| range.contains(pos))
+            .collect();
+        assert_eq!(refreshed, expected);
+
+        let center = IVec3::new(5, 0, 0);
+        app.world.insert_resource(CurrentChunk(center));
+        app.update();
+        let unloaded: HashSet<IVec3> = app.world.resource::<ChunkEvents>().unloaded
+            .iter()
+            .copied()
+            .collect();
+        assert_eq!(unloaded, range.difference(&chunks_in_range(center)).copied().collect());
+    }
+
+    #[test]
+    fn decorations_are_placed_once() {
+        let mut app = test_app("decorations-once");
+        app.insert_resource(WorldSeed(1234));
+        app.update();
+        finish_generation(&mut app);
+
+        // A decoration block in chunk `target`, which gets unloaded when moving to `center`
+        let center = IVec3::new(3, 0, 0);
+        let (range, moved_range) = (chunks_in_range(IVec3::ZERO), chunks_in_range(center));
+        let chunk_map = app.world.resource::<ChunkMap>();
+        let pos = range.difference(&moved_range)
+            .flat_map(|target| {
+                let chunk = app.world.get::<MyChunk>(chunk_map.chunks[target]).unwrap();
+                let origin = *target * MyChunk::SIDE as i32;
+                chunk.iter_non_empty()
+                    .filter(|(_, block)| *block == Block::LEAVES)
+                    .map(move |(local, _)| origin + local)
+            })
+            .next()
+            .expect("no decorations in unloaded chunks");
+
+        // Broken by the player
+        let (target, index) = split_position(pos);
+        let entity = chunk_map.chunks[&target];
+        app.world.get_mut::<MyChunk>(entity).unwrap().voxels.set(index, Block::AIR);
+        app.world.entity_mut(entity).insert(ChunkModified);
+
+        app.world.insert_resource(CurrentChunk(center));
+        app.update();
+        app.world.insert_resource(CurrentChunk(IVec3::ZERO));
+        app.update();
+        finish_generation(&mut app);
+
+        let entity = app.world.resource::<ChunkMap>().chunks[&target];
+        assert_eq!(app.world.get::<MyChunk>(entity).unwrap().voxels.get(index), Block::AIR);
+    }
+
+    /// Air everywhere, but for one stone block
+    struct StoneAt(IVec3);
+
+    impl TerrainGenerator for StoneAt {
+        fn generate(&self, position: IVec3) -> MyChunk {
+            let mut chunk = MyChunk::default().with_position(position);
+            chunk.set(self.0 - position * MyChunk::SIDE as i32, Block::STONE);
+            chunk
+        }
+
+        fn biome(&self, _x: i32, _z: i32) -> Biome {
+            Biome::Plains
+        }
+    }
+
+    #[test]
+    fn refreshed_meshes_get_new_bounds() {
+        let mut app = test_app("bounds");
+        enable_meshing(&mut app);
+        let mut infos = vec![BlockInfo::default(); Block::STONE.0 as usize + 1];
+        infos[Block::STONE.0 as usize] = BlockInfo {
+            block: Block::STONE,
+            visibility: Visibility::Opaque,
+            textures: [Some(0); 6],
+            overlays: [None; 6],
+        };
+        app
+            .insert_resource(BlockInfos::new(infos))
+            .add_systems(PostUpdate, calculate_bounds);
+        app.update();
+        // Chunks around the new center all come from this generator
+        let center = IVec3::new(1000, 1, 0);
+        let origin = center * MyChunk::SIDE as i32;
+        app.world.insert_resource(WorldGenerator(Arc::new(StoneAt(origin))));
+        app.world.insert_resource(CurrentChunk(center));
+        app.update();
+        finish_generation(&mut app);
+        finish_meshing(&mut app);
+        app.update();
+
+        let entity = app.world.resource::<ChunkMap>().chunks[&center];
+        let opaque = app.world.get::<ChunkSubMeshes>(entity).unwrap().opaque;
+        let aabb = *app.world.get::<Aabb>(opaque).unwrap();
+        assert_eq!(Vec3::from(aabb.max()), Vec3::ONE);
+
+        app.world.get_mut::<MyChunk>(entity).unwrap().set(IVec3::splat(15), Block::STONE);
+        finish_meshing(&mut app);
+        app.update();
+        let aabb = *app.world.get::<Aabb>(opaque).unwrap();
+        assert_eq!(Vec3::from(aabb.min()), Vec3::ZERO);
+        assert_eq!(Vec3::from(aabb.max()), Vec3::splat(16.0));
+    }
+
+    #[test]
+    fn saved_chunks_are_not_modified_by_loading() {
+        let mut app = test_app("saved-unmodified");
+        let position = IVec3::new(0, 1, 0);
+        let mut chunk = MyChunk::default().with_position(position);
+        // Decorations never replace dirt
+        chunk.fill(Block::DIRT);
+        app.world.resource::<ChunkStorage>().0.store([&chunk]).unwrap();
+        app.update();
+        finish_generation(&mut app);
+
+        let entity = app.world.resource::<ChunkMap>().chunks[&position];
+        assert_eq!(app.world.get::<MyChunk>(entity).unwrap().is_uniform(), Some(Block::DIRT));
+        assert!(app.world.get::<ChunkModified>(entity).is_none());
+    }
+}
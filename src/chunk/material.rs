@@ -2,21 +2,29 @@ use bevy::prelude::*;
 use bevy::render::render_resource::AsBindGroup;
 use bevy::render::mesh::MeshVertexAttribute;
 use bevy::render::render_resource::VertexFormat;
+use super::texture::{BlockTextureArray, TextureArrayPlugin};
 
 pub const ATTRIBUTE_BASE_VOXEL_INDICES: MeshVertexAttribute =
     MeshVertexAttribute::new("BaseVoxelIndices", 988540917, VertexFormat::Uint32);
 pub const ATTRIBUTE_OVERLAY_VOXEL_INDICES: MeshVertexAttribute =
     MeshVertexAttribute::new("OverlayVoxelIndices", 593015852, VertexFormat::Uint32);
+/// Sky and block light of a face, from 0 to 1
+pub const ATTRIBUTE_LIGHT: MeshVertexAttribute =
+    MeshVertexAttribute::new("Light", 271845093, VertexFormat::Float32x2);
+/// Ambient occlusion of a vertex, from 0 (darkest) to 1
+pub const ATTRIBUTE_AO: MeshVertexAttribute =
+    MeshVertexAttribute::new("AmbientOcclusion", 640183722, VertexFormat::Float32);
+
+/// Overlay layer index of faces that have no overlay,
+/// must match `NO_OVERLAY` in `chunk.wgsl`
+pub const NO_OVERLAY: u32 = u32::MAX;
 
 #[derive(Asset, AsBindGroup, Clone, TypePath)]
 pub struct ChunkMaterial {
     #[texture(0, dimension="2d_array")]
     #[sampler(1)]
     pub texture: Handle<Image>,
-
-    #[texture(2, dimension="2d_array")]
-    #[sampler(3)]
-    pub pbr_texture: Handle<Image>
+    pub alpha_mode: AlphaMode,
 }
 
 impl Material for ChunkMaterial {
@@ -29,7 +37,7 @@ impl Material for ChunkMaterial {
     }
 
     fn alpha_mode(&self) -> AlphaMode {
-        AlphaMode::Blend
+        self.alpha_mode
     }
 
     fn specialize(
@@ -43,10 +51,60 @@ impl Material for ChunkMaterial {
             Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
             Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
             Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(3),
             ATTRIBUTE_BASE_VOXEL_INDICES.at_shader_location(7),
             ATTRIBUTE_OVERLAY_VOXEL_INDICES.at_shader_location(8),
+            ATTRIBUTE_LIGHT.at_shader_location(9),
+            ATTRIBUTE_AO.at_shader_location(10),
         ])?;
         descriptor.vertex.buffers = vec![vertex_layout];
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Materials shared by every chunk mesh,
+/// inserted once the texture array is built
+#[derive(Resource)]
+pub struct ChunkMaterials {
+    pub opaque: Handle<ChunkMaterial>,
+    /// Blended, for faces of [`super::block::Visibility::Transparent`] blocks
+    pub transparent: Handle<ChunkMaterial>,
+}
+
+pub struct ChunkMaterialPlugin;
+
+impl Plugin for ChunkMaterialPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_plugins((
+                MaterialPlugin::<ChunkMaterial>::default(),
+                TextureArrayPlugin
+            ))
+            .add_systems(Update, setup_chunk_material.run_if(resource_exists_and_changed::<BlockTextureArray>));
+    }
+}
+
+/// Creates the chunk materials, or swaps their texture when blocks are reloaded
+fn setup_chunk_material(
+    mut commands: Commands,
+    texture_array: Res<BlockTextureArray>,
+    chunk_materials: Option<Res<ChunkMaterials>>,
+    mut materials: ResMut<Assets<ChunkMaterial>>
+) {
+    if let Some(chunk_materials) = chunk_materials {
+        for handle in [&chunk_materials.opaque, &chunk_materials.transparent] {
+            if let Some(material) = materials.get_mut(handle) {
+                material.texture = texture_array.0.clone();
+            }
+        }
+        return;
+    }
+    let mut material = |alpha_mode| materials.add(ChunkMaterial {
+        texture: texture_array.0.clone(),
+        alpha_mode,
+    });
+    commands.insert_resource(ChunkMaterials {
+        opaque: material(AlphaMode::Opaque),
+        transparent: material(AlphaMode::Blend),
+    });
+}
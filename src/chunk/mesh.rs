@@ -6,6 +6,18 @@ use bevy::render::{
         VertexAttributeValues
     }, render_asset::RenderAssetUsages
 };
+use super::face::Face;
+use super::light::MAX_LIGHT;
+use super::material::{
+    ATTRIBUTE_BASE_VOXEL_INDICES,
+    ATTRIBUTE_OVERLAY_VOXEL_INDICES,
+    ATTRIBUTE_LIGHT,
+    ATTRIBUTE_AO,
+    NO_OVERLAY
+};
+
+/// Edge length of a voxel in world units
+pub const VOXEL_SIZE: f32 = 1.0;
 
 pub fn new_mesh(
     positions: Vec<[f32; 3]>,
@@ -32,4 +44,114 @@ pub fn new_mesh(
             Mesh::ATTRIBUTE_NORMAL,
             VertexAttributeValues::Float32x3(normals)
         )
-}
\ No newline at end of file
+}
+
+/// Assembles a chunk mesh out of the faces produced by a mesher,
+/// `tint` gives the overlay color of a face at the given position
+pub fn build_mesh(faces: &[Face], tint: impl Fn(UVec3) -> [f32; 4]) -> Mesh {
+    let mut positions = Vec::with_capacity(faces.len() * 4);
+    let mut indices = Vec::with_capacity(faces.len() * 6);
+    let mut normals = Vec::with_capacity(faces.len() * 4);
+    let mut uvs = Vec::with_capacity(faces.len() * 4);
+    let mut base_indices = Vec::with_capacity(faces.len() * 4);
+    let mut overlay_indices = Vec::with_capacity(faces.len() * 4);
+    let mut tints = Vec::with_capacity(faces.len() * 4);
+    let mut lights = Vec::with_capacity(faces.len() * 4);
+    let mut ao = Vec::with_capacity(faces.len() * 4);
+
+    for face in faces {
+        let base = face.texture.unwrap_or_default();
+        let overlay = face.overlay.unwrap_or(NO_OVERLAY);
+        let light = [face.light.sky(), face.light.block()].map(|level| level as f32 / MAX_LIGHT as f32);
+
+        indices.extend_from_slice(&face.indices(positions.len() as u32));
+        positions.extend_from_slice(&face.positions(VOXEL_SIZE));
+        normals.extend_from_slice(&face.normals());
+        uvs.extend_from_slice(&face.uvs());
+        base_indices.extend_from_slice(&[base; 4]);
+        overlay_indices.extend_from_slice(&[overlay; 4]);
+        tints.extend_from_slice(&[tint(face.position); 4]);
+        lights.extend_from_slice(&[light; 4]);
+        ao.extend(face.ao.map(|corner| corner as f32 / 3.0));
+    }
+
+    new_mesh(positions, indices, uvs, normals)
+        .with_inserted_attribute(
+            ATTRIBUTE_BASE_VOXEL_INDICES,
+            VertexAttributeValues::Uint32(base_indices)
+        )
+        .with_inserted_attribute(
+            ATTRIBUTE_OVERLAY_VOXEL_INDICES,
+            VertexAttributeValues::Uint32(overlay_indices)
+        )
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_COLOR,
+            VertexAttributeValues::Float32x4(tints)
+        )
+        .with_inserted_attribute(
+            ATTRIBUTE_LIGHT,
+            VertexAttributeValues::Float32x2(lights)
+        )
+        .with_inserted_attribute(
+            ATTRIBUTE_AO,
+            VertexAttributeValues::Float32(ao)
+        )
+}
+
+/// Faces of a blended mesh, kept to redo its indices
+/// so that faces farther from the camera are drawn first
+#[derive(Component)]
+pub struct TransparentFaces(Vec<(Vec3, [u32; 6])>);
+
+impl TransparentFaces {
+    /// `faces` in the order they were passed to [`build_mesh`]
+    pub fn new(faces: &[Face]) -> Self {
+        Self(faces.iter().enumerate().map(|(i, face)| {
+            let center = face.positions(VOXEL_SIZE)
+                .into_iter()
+                .map(Vec3::from)
+                .sum::<Vec3>() / 4.0;
+            (center, face.indices(i as u32 * 4))
+        }).collect())
+    }
+
+    /// Sorts back to front as seen from `eye`, in mesh space,
+    /// and returns the new indices
+    pub fn sort(&mut self, eye: Vec3) -> Vec<u32> {
+        self.0.sort_by(|(a, _), (b, _)| {
+            b.distance_squared(eye).total_cmp(&a.distance_squared(eye))
+        });
+        self.0.iter().flat_map(|(_, indices)| *indices).collect()
+    }
+}
+
+/// Edges of a box from the origin to `size`, drawn as lines
+pub fn line_box(size: Vec3) -> Mesh {
+    let Vec3 { x, y, z } = size;
+    let positions = vec![
+        [0.0, 0.0, 0.0],
+        [x, 0.0, 0.0],
+        [0.0, 0.0, z],
+        [x, 0.0, z],
+        [0.0, y, 0.0],
+        [x, y, 0.0],
+        [0.0, y, z],
+        [x, y, z],
+    ];
+    let indices = vec![
+        0, 1, 0, 2, 3, 1, 3, 2, // bottom
+        4, 5, 4, 6, 7, 5, 7, 6, // top
+        0, 4, 1, 5, 2, 6, 3, 7 // sides
+    ];
+    Mesh::new(
+        PrimitiveTopology::LineList,
+        RenderAssetUsages::RENDER_WORLD
+    )
+        .with_inserted_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            VertexAttributeValues::Float32x3(positions)
+        )
+        .with_inserted_indices(
+            Indices::U32(indices)
+        )
+}
@@ -1,12 +1,29 @@
 mod face;
 mod mesh;
-mod block;
+pub mod block;
 mod material;
+mod texture;
+pub mod registry;
+pub mod terrain;
+mod biome;
+mod decoration;
+pub mod region;
+mod greedy;
+mod palette;
+mod view;
+mod light;
+mod raycast;
+pub mod voxel_world;
+pub mod interaction;
+mod outline;
+mod debug;
 pub mod loader;
 use bevy::prelude::*;
-use face::Face;
-use mesh::new_mesh;
+use face::{Face, Side};
+use greedy::greedy_mesh;
 use block::{Visibility, Voxel, Block};
+use light::Light;
+use palette::PaletteStorage;
 
 const CHUNK_SIDE: usize = 16;
 const CHUNK_LEN: usize = CHUNK_SIDE * CHUNK_SIDE * CHUNK_SIDE;
@@ -37,41 +54,74 @@ trait Chunk {
     }
 
     fn get(&self, pos: IVec3) -> Self::Output;
+
+    /// Light shining on faces that look at `pos`
+    fn light(&self, _pos: IVec3) -> Light {
+        Light::SKY
+    }
+
+    /// The voxel filling the whole chunk, if there is only one
+    fn is_uniform(&self) -> Option<Self::Output>
+    where
+        Self::Output: PartialEq
+    {
+        let first = self.get(IVec3::ZERO);
+        (1..Self::size()).all(|i| {
+            let (x, y, z) = Self::delinearize(i);
+            self.get(IVec3::new(x as i32, y as i32, z as i32)) == first
+        }).then_some(first)
+    }
+
+    /// Every voxel is the default one
+    fn is_empty(&self) -> bool
+    where
+        Self::Output: Default + PartialEq
+    {
+        self.is_uniform().is_some_and(|voxel| voxel == Self::Output::default())
+    }
+
+    /// Positions and values of the voxels that are not the default one
+    fn iter_non_empty(&self) -> impl Iterator<Item = (IVec3, Self::Output)> + '_
+    where
+        Self::Output: Default + PartialEq
+    {
+        (0..Self::size()).filter_map(|i| {
+            let (x, y, z) = Self::delinearize(i);
+            let pos = IVec3::new(x as i32, y as i32, z as i32);
+            let voxel = self.get(pos);
+            (voxel != Self::Output::default()).then_some((pos, voxel))
+        })
+    }
 }
 
-#[derive(Component)]
-struct MyChunk {
+/// [`Chunk`] whose voxels can be edited
+trait ChunkMut: Chunk {
+    /// Positions outside of the chunk are ignored
+    fn set(&mut self, pos: IVec3, voxel: Self::Output);
+
+    fn fill(&mut self, voxel: Self::Output);
+}
+
+#[derive(Component, Clone)]
+pub struct MyChunk {
     position: IVec3,
-    voxels: [Block; CHUNK_LEN]
+    /// Chunks of a single block, like air or deep stone, take a few bytes
+    voxels: PaletteStorage<Block>,
+    /// Computed after loading, so it is never saved
+    light: [Light; CHUNK_LEN]
 }
 
 impl Default for MyChunk {
     fn default() -> Self {
         Self {
             position: IVec3::ZERO,
-            voxels: [Block::default(); CHUNK_LEN]
+            voxels: PaletteStorage::new(CHUNK_LEN, Block::default()),
+            light: [Light::default(); CHUNK_LEN]
         }
     }
 }
 
 impl MyChunk {
-    fn flat(level: usize) -> Self {
-        let mut voxels = [Block::default(); CHUNK_LEN];
-        for i in 0..Self::size() {
-            let (_, y, _) = Self::delinearize(i);
-            if y < level - 1 {
-                voxels[i] = Block::Stone;
-            }
-            else if y == level - 1 {
-                voxels[i] = Block::Dirt;
-            }
-        }
-        Self {
-            position: IVec3::ZERO,
-            voxels
-        }
-    }
-
     fn with_position(mut self, position: IVec3) -> Self {
         self.position = position;
         self
@@ -89,9 +139,103 @@ impl Chunk for MyChunk {
         {
             Self::Output::default()
         } else {
-            self.voxels[Self::linearize(pos.x as usize, pos.y as usize, pos.z as usize)]
+            self.voxels.get(Self::linearize(pos.x as usize, pos.y as usize, pos.z as usize))
         }
     }
+
+    fn light(&self, pos: IVec3) -> Light {
+        if pos.min_element() < 0
+        || pos.max_element() >= Self::SIDE as i32
+        {
+            Light::default()
+        } else {
+            self.light[Self::linearize(pos.x as usize, pos.y as usize, pos.z as usize)]
+        }
+    }
+
+    fn is_uniform(&self) -> Option<Self::Output> {
+        self.voxels.uniform()
+    }
+
+    fn iter_non_empty(&self) -> impl Iterator<Item = (IVec3, Self::Output)> + '_ {
+        // Air chunks are skipped without looking at their voxels
+        let len = if self.is_empty() { 0 } else { CHUNK_LEN };
+        (0..len).filter_map(|i| {
+            let voxel = self.voxels.get(i);
+            let (x, y, z) = Self::delinearize(i);
+            (voxel != Block::AIR).then_some((IVec3::new(x as i32, y as i32, z as i32), voxel))
+        })
+    }
+}
+
+impl ChunkMut for MyChunk {
+    fn set(&mut self, pos: IVec3, voxel: Self::Output) {
+        if pos.min_element() >= 0 && pos.max_element() < Self::SIDE as i32 {
+            self.voxels.set(Self::linearize(pos.x as usize, pos.y as usize, pos.z as usize), voxel);
+        }
+    }
+
+    fn fill(&mut self, voxel: Self::Output) {
+        self.voxels.fill(voxel);
+    }
+}
+
+/// Chunk made of a single voxel that shows no faces:
+/// it is empty, or every voxel next to it hides its faces.
+/// Only the borders are looked at, so meshing can skip it cheaply
+fn is_hidden<C, T>(chunk: &C) -> bool
+where
+    C: Chunk<Output = T>,
+    T: Voxel
+{
+    if chunk.is_empty() {
+        return true;
+    }
+    let Some(voxel) = chunk.is_uniform() else {
+        return false;
+    };
+
+    let len = C::SIDE as i32;
+    Side::ALL.into_iter().all(|side| {
+        let axis = side.axis();
+        let (u_axis, v_axis) = side.uv_axes();
+        let layer = if side.offset()[axis] > 0 { len - 1 } else { 0 };
+        (0..len).all(|u| (0..len).all(|v| {
+            let mut pos = IVec3::ZERO;
+            pos[axis] = layer;
+            pos[u_axis] = u;
+            pos[v_axis] = v;
+            !voxel.visible(&chunk.get(pos + side.offset()))
+        }))
+    })
+}
+
+/// Ambient occlusion of the corners of a face, see [`Face::ao`].
+/// Each corner is darkened by the opaque voxels touching it in front of the face
+fn face_ao<C, T>(chunk: &C, pos: IVec3, side: Side) -> [u8; 4]
+where
+    C: Chunk<Output = T>,
+    T: Voxel
+{
+    let front = pos + side.offset();
+    let (u_axis, v_axis) = side.uv_axes();
+    let occludes = |pos: IVec3| chunk.get(pos).visibility() == Visibility::Opaque;
+
+    side.positions().map(|corner| {
+        let mut du = IVec3::ZERO;
+        let mut dv = IVec3::ZERO;
+        du[u_axis] = if corner[u_axis] > 0.5 { 1 } else { -1 };
+        dv[v_axis] = if corner[v_axis] > 0.5 { 1 } else { -1 };
+
+        let side1 = occludes(front + du);
+        let side2 = occludes(front + dv);
+        let corner = occludes(front + du + dv);
+        if side1 && side2 {
+            0
+        } else {
+            3 - side1 as u8 - side2 as u8 - corner as u8
+        }
+    })
 }
 
 fn simple_mesh<C, T>(chunk: &C) -> Vec<Face>
@@ -102,6 +246,9 @@ where
     assert!(C::SIDE >= 2, "chunk side is too small");
 
     let mut buffer = Vec::new();
+    if is_hidden(chunk) {
+        return buffer;
+    }
     for i in 0..C::size() {
         let (x, y, z) = C::delinearize(i);
 
@@ -124,9 +271,16 @@ where
         for (i, neighbor) in neighbors.into_iter().enumerate() {
             let generate = voxel.visible(&neighbor);
             if generate {
+                let side = Side::from(i);
                 buffer.push(Face {
-                    side: i.into(),
+                    side,
                     position: pos.as_uvec3(),
+                    size: UVec2::ONE,
+                    texture: voxel.texture(side),
+                    overlay: voxel.overlay(side),
+                    light: chunk.light(pos + side.offset()),
+                    ao: face_ao(chunk, pos, side),
+                    transparent: voxel.visibility() == Visibility::Transparent,
                 });
             }
         }
@@ -134,71 +288,27 @@ where
     buffer
 }
 
-// ----------------------------------------
-
-fn render_chunk(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    asset_server: Res<AssetServer>
-) {
-    let chunks = [
-        MyChunk::flat(1)
-            .with_position(IVec3::new(0, 0, 0)),
-        MyChunk::flat(2)
-            .with_position(IVec3::new(-1, 0, 0)),
-        MyChunk::flat(3)
-            .with_position(IVec3::new(0, 0, -1)),
-        MyChunk::flat(4)
-            .with_position(IVec3::new(-1, 0, -1)),
-    ];
-    for chunk in chunks {
-        let faces = simple_mesh(&chunk);
-    
-        let mut positions = Vec::with_capacity(faces.len() * 4);
-        let mut indices = Vec::with_capacity(faces.len() * 6);
-        let mut normals = Vec::with_capacity(faces.len() * 4);
-        let mut uvs = Vec::with_capacity(faces.len() * 4);
-    
-        for face in faces {
-            indices.extend_from_slice(&face.indices(positions.len() as u32));
-            positions.extend_from_slice(&face.positions(1.0));
-            normals.extend_from_slice(&face.normals());
-            uvs.extend_from_slice(&face.uvs());
-        }
-    
-        let mesh = new_mesh(positions, indices, uvs, normals);
-        let mesh_handle = meshes.add(mesh);
-        // let texture = asset_server.load("dirt.png");
-        let texture = asset_server.load("stone.png");
-        // let dirt_texture = asset_server.load("dirt.png");
-        // let stone_texture = asset_server.load("stone.png");
-
-        let name = format!("Chunk at {}, {}, {}", chunk.position.x, chunk.position.y, chunk.position.z);
-        commands.spawn((
-            PbrBundle {
-                mesh: mesh_handle,
-                material: materials.add(StandardMaterial {
-                    base_color_texture: Some(texture),
-                    unlit: true, // remove later
-                    ..default()
-                }),
-                transform: Transform::default()
-                    .with_translation(chunk.position.as_vec3() * MyChunk::SIDE as f32),
-                ..default()
-            },
-            chunk,
-            Name::new(name)
-        ));
-    }
+/// Meshing algorithm used for chunks, can be switched at runtime
+#[derive(Resource, Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[reflect(Resource)]
+pub enum Mesher {
+    /// One quad per visible voxel face, see [`simple_mesh`]
+    Simple,
+    /// Coplanar faces merged into rectangles, see [`greedy_mesh`]
+    #[default]
+    Greedy
 }
 
-
-pub struct SpawnChunkPlugin;
-
-impl Plugin for SpawnChunkPlugin {
-    fn build(&self, app: &mut App) {
-        app
-            .add_systems(Startup, render_chunk);
+impl Mesher {
+    /// `tint` is the overlay color at a position, faces of different tints are not merged
+    fn mesh<C, T>(&self, chunk: &C, tint: impl Fn(UVec3) -> [f32; 4]) -> Vec<Face>
+    where
+        C: Chunk<Output = T>,
+        T: Voxel
+    {
+        match self {
+            Self::Simple => simple_mesh(chunk),
+            Self::Greedy => greedy_mesh(chunk, tint),
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+use crate::keybinds::KeyBindsResource;
+use super::interaction::TargetBlock;
+use super::mesh::{line_box, VOXEL_SIZE};
+
+/// How much the outline is bigger than the block, so it is not hidden by its faces
+const OUTLINE_MARGIN: f32 = 0.005;
+
+/// Whether the targeted block is outlined
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct ShowBlockOutline(pub bool);
+
+impl Default for ShowBlockOutline {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+#[derive(Component)]
+struct BlockOutline;
+
+pub struct BlockOutlinePlugin;
+
+impl Plugin for BlockOutlinePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<ShowBlockOutline>()
+            .register_type::<ShowBlockOutline>()
+            .add_systems(Startup, spawn_block_outline)
+            .add_systems(Update, (
+                toggle_block_outline,
+                update_block_outline
+            ).chain());
+    }
+}
+
+fn spawn_block_outline(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>
+) {
+    commands.spawn((
+        BlockOutline,
+        PbrBundle {
+            mesh: meshes.add(line_box(Vec3::splat(VOXEL_SIZE + OUTLINE_MARGIN * 2.0))),
+            material: materials.add(StandardMaterial {
+                base_color: Color::BLACK,
+                unlit: true,
+                ..default()
+            }),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Name::new("Block outline")
+    ));
+}
+
+fn toggle_block_outline(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keybinds: Res<KeyBindsResource>,
+    mut show: ResMut<ShowBlockOutline>
+) {
+    if keyboard_input.just_pressed(keybinds.toggle_block_outline) {
+        show.0 = !show.0;
+    }
+}
+
+fn update_block_outline(
+    mut outline_query: Query<(&mut Transform, &mut Visibility), With<BlockOutline>>,
+    target: Res<TargetBlock>,
+    show: Res<ShowBlockOutline>
+) {
+    let Ok((mut transform, mut visibility)) = outline_query.get_single_mut() else {
+        return;
+    };
+    match target.0 {
+        Some(hit) if show.0 => {
+            transform.translation = hit.position.as_vec3() * VOXEL_SIZE - OUTLINE_MARGIN;
+            *visibility = Visibility::Visible;
+        },
+        _ => *visibility = Visibility::Hidden,
+    }
+}
@@ -0,0 +1,266 @@
+/// Values stored as indices into a palette of the distinct values they contain.
+/// Indices take as few bits as the palette needs and are widened when it grows,
+/// while there is a single value no indices are stored at all
+#[derive(Clone, Debug)]
+pub struct PaletteStorage<T> {
+    palette: Vec<T>,
+    /// How many indices point at each palette value,
+    /// values no longer used are replaced by new ones
+    counts: Vec<usize>,
+    /// Bits per index, a power of two so that indices never straddle two words.
+    /// `0` while the palette has a single value
+    bits: u32,
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl<T: Copy + Eq> PaletteStorage<T> {
+    /// `len` copies of `value`
+    pub fn new(len: usize, value: T) -> Self {
+        Self {
+            palette: vec![value],
+            counts: vec![len],
+            bits: 0,
+            words: Vec::new(),
+            len,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> T {
+        self.palette[self.palette_index(index)]
+    }
+
+    pub fn set(&mut self, index: usize, value: T) {
+        assert!(index < self.len, "index out of bounds");
+        let old = self.palette_index(index);
+        if self.palette[old] == value {
+            return;
+        }
+        self.counts[old] -= 1;
+
+        let palette_index = match self.palette.iter().position(|v| *v == value) {
+            Some(palette_index) => palette_index,
+            None => match self.counts.iter().position(|count| *count == 0) {
+                Some(unused) => {
+                    self.palette[unused] = value;
+                    unused
+                },
+                None => {
+                    self.palette.push(value);
+                    self.counts.push(0);
+                    let bits = Self::bits_for(self.palette.len());
+                    if bits != self.bits {
+                        self.resize(bits);
+                    }
+                    self.palette.len() - 1
+                }
+            }
+        };
+        self.counts[palette_index] += 1;
+        // Back to a single value, the indices are not needed anymore
+        if self.counts[palette_index] == self.len {
+            self.fill(value);
+            return;
+        }
+        self.set_palette_index(index, palette_index);
+    }
+
+    /// Replaces every value, dropping the indices
+    pub fn fill(&mut self, value: T) {
+        *self = Self::new(self.len, value);
+    }
+
+    /// The only value stored, if there is one
+    pub fn uniform(&self) -> Option<T> {
+        (self.bits == 0).then_some(self.palette[0])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        (0..self.len).map(|i| self.get(i))
+    }
+
+    /// Bytes used by the storage, including its heap allocations
+    #[cfg(test)]
+    pub fn memory_use(&self) -> usize {
+        use std::mem::size_of;
+        size_of::<Self>()
+            + self.palette.capacity() * size_of::<T>()
+            + self.counts.capacity() * size_of::<usize>()
+            + self.words.capacity() * size_of::<u64>()
+    }
+
+    /// Smallest index width for a palette of `len` values
+    fn bits_for(len: usize) -> u32 {
+        match len {
+            0 | 1 => 0,
+            _ => (usize::BITS - (len - 1).leading_zeros()).next_power_of_two(),
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    /// Word holding an index and the position of its lowest bit in there.
+    /// Shifts instead of divisions, this is on the path of every `get`
+    fn locate(&self, index: usize) -> (usize, u32) {
+        let bit = index * self.bits as usize;
+        (bit >> 6, (bit & 63) as u32)
+    }
+
+    fn palette_index(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let (word, shift) = self.locate(index);
+        ((self.words[word] >> shift) & self.mask()) as usize
+    }
+
+    fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+        let (word, shift) = self.locate(index);
+        let mask = self.mask();
+        let word = &mut self.words[word];
+        *word = (*word & !(mask << shift)) | ((palette_index as u64 & mask) << shift);
+    }
+
+    /// Copies every index into words of the new width
+    fn resize(&mut self, bits: u32) {
+        let indices: Vec<usize> = (0..self.len).map(|i| self.palette_index(i)).collect();
+        self.bits = bits;
+        self.words = vec![0; (self.len * bits as usize).div_ceil(u64::BITS as usize)];
+        for (i, palette_index) in indices.into_iter().enumerate() {
+            self.set_palette_index(i, palette_index);
+        }
+    }
+}
+
+/// Storages holding the same values are equal, whatever their palettes look like
+impl<T: Copy + Eq> PartialEq for PaletteStorage<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hint::black_box;
+    use std::mem::size_of;
+    use std::time::Instant;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use super::*;
+    use super::super::CHUNK_LEN;
+    use super::super::block::Block;
+
+    #[test]
+    fn widens_indices_as_palette_grows() {
+        let mut storage = PaletteStorage::new(CHUNK_LEN, 0u16);
+        let mut expected = vec![0u16; CHUNK_LEN];
+        // Palette sizes and the index width they need
+        for (values, bits) in [(2, 1), (4, 2), (5, 4), (16, 4), (17, 8), (256, 8)] {
+            for value in 0..values {
+                let index = (value as usize * 997 + values as usize) % CHUNK_LEN;
+                storage.set(index, value);
+                expected[index] = value;
+            }
+            assert_eq!(storage.bits, bits, "{values} values");
+            assert!((0..CHUNK_LEN).all(|i| storage.get(i) == expected[i]), "{values} values");
+        }
+    }
+
+    #[test]
+    fn matches_array_after_random_writes() {
+        let mut rng = StdRng::seed_from_u64(24);
+        let mut storage = PaletteStorage::new(CHUNK_LEN, Block::AIR);
+        let mut array = [Block::AIR; CHUNK_LEN];
+        for _ in 0..20_000 {
+            let (index, block) = (rng.gen_range(0..CHUNK_LEN), Block(rng.gen_range(0..40)));
+            storage.set(index, block);
+            array[index] = block;
+        }
+        assert!((0..CHUNK_LEN).all(|i| storage.get(i) == array[i]));
+    }
+
+    #[test]
+    fn fill_and_uniform() {
+        let mut storage = PaletteStorage::new(CHUNK_LEN, Block::AIR);
+        assert_eq!(storage.uniform(), Some(Block::AIR));
+        storage.set(10, Block::STONE);
+        assert_eq!(storage.uniform(), None);
+        // Overwritten values do not count
+        storage.set(10, Block::AIR);
+        assert_eq!(storage.uniform(), Some(Block::AIR));
+
+        storage.fill(Block::STONE);
+        assert_eq!(storage.bits, 0);
+        assert_eq!(storage.uniform(), Some(Block::STONE));
+        assert_eq!(storage.get(CHUNK_LEN - 1), Block::STONE);
+    }
+
+    #[test]
+    fn reuses_entries_of_overwritten_values() {
+        let mut storage = PaletteStorage::new(CHUNK_LEN, Block::AIR);
+        storage.set(0, Block::STONE);
+        storage.set(1, Block::DIRT);
+        storage.set(0, Block::AIR);
+        storage.set(2, Block::SAND);
+        assert_eq!(storage.palette, vec![Block::AIR, Block::SAND, Block::DIRT]);
+        assert_eq!([storage.get(0), storage.get(1), storage.get(2)], [Block::AIR, Block::DIRT, Block::SAND]);
+    }
+
+    #[test]
+    fn uses_less_memory_than_array() {
+        let array = size_of::<[Block; CHUNK_LEN]>();
+        let mut storage = PaletteStorage::new(CHUNK_LEN, Block::STONE);
+        assert!(storage.memory_use() * 50 < array, "uniform storage uses {} bytes", storage.memory_use());
+
+        // 4 bits per voxel
+        for i in 0..CHUNK_LEN {
+            storage.set(i, Block((i % 10) as u16));
+        }
+        assert!(storage.memory_use() * 3 < array, "storage of 10 blocks uses {} bytes", storage.memory_use());
+    }
+
+    /// Compares memory use and `get` speed with a plain array, run it with
+    /// `cargo test --release palette_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn palette_benchmark() {
+        const PASSES: usize = 1000;
+        let mut rng = StdRng::seed_from_u64(24);
+        println!("array: {} bytes", size_of::<[Block; CHUNK_LEN]>());
+        for kinds in [1, 2, 10, 100] {
+            let mut storage = PaletteStorage::new(CHUNK_LEN, Block::AIR);
+            let mut array = Box::new([Block::AIR; CHUNK_LEN]);
+            for i in 0..CHUNK_LEN {
+                let block = Block(rng.gen_range(0..kinds));
+                storage.set(i, block);
+                array[i] = block;
+            }
+
+            let start = Instant::now();
+            let mut array_sum = 0u64;
+            for _ in 0..PASSES {
+                for i in 0..CHUNK_LEN {
+                    array_sum += black_box(&array)[i].0 as u64;
+                }
+            }
+            let array_time = start.elapsed().as_secs_f64() * 1e9 / (PASSES * CHUNK_LEN) as f64;
+
+            let start = Instant::now();
+            let mut palette_sum = 0u64;
+            for _ in 0..PASSES {
+                for i in 0..CHUNK_LEN {
+                    palette_sum += black_box(&storage).get(i).0 as u64;
+                }
+            }
+            let palette_time = start.elapsed().as_secs_f64() * 1e9 / (PASSES * CHUNK_LEN) as f64;
+
+            assert_eq!(array_sum, palette_sum);
+            println!(
+                "{kinds} blocks: palette {} bytes, get {palette_time:.2}ns, array get {array_time:.2}ns",
+                storage.memory_use()
+            );
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use bevy::prelude::*;
+use super::face::Side;
+
+/// Voxel hit by [`raycast`]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RayHit {
+    /// World position of the voxel
+    pub position: IVec3,
+    /// Side of the voxel the ray entered through
+    pub side: Side,
+    pub distance: f32,
+}
+
+impl RayHit {
+    /// Position of the voxel in front of the hit side, where blocks are placed
+    pub fn adjacent(&self) -> IVec3 {
+        self.position + self.side.offset()
+    }
+}
+
+/// Walks voxels along a ray with DDA, voxel by voxel in the order they are crossed,
+/// until `is_hit` returns `true` or `max_distance` is reached.
+/// The voxel containing `origin` is never hit
+pub fn raycast(
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    mut is_hit: impl FnMut(IVec3) -> bool
+) -> Option<RayHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+
+    let mut voxel = origin.floor().as_ivec3();
+    let step = direction.signum().as_ivec3();
+    // Distance along the ray to cross one voxel on each axis
+    let delta = direction.recip().abs();
+    // Distance along the ray to the first voxel border on each axis
+    let mut next = Vec3::from_array(std::array::from_fn(|axis| {
+        if direction[axis] == 0.0 {
+            return f32::INFINITY;
+        }
+        let border = if direction[axis] > 0.0 {
+            voxel[axis] as f32 + 1.0 - origin[axis]
+        } else {
+            origin[axis] - voxel[axis] as f32
+        };
+        border * delta[axis]
+    }));
+
+    loop {
+        let axis = if next.x < next.y && next.x < next.z {
+            0
+        } else if next.y < next.z {
+            1
+        } else {
+            2
+        };
+        let distance = next[axis];
+        if distance > max_distance {
+            return None;
+        }
+        voxel[axis] += step[axis];
+        next[axis] += delta[axis];
+
+        if is_hit(voxel) {
+            let side = match (axis, step[axis] > 0) {
+                (0, true) => Side::Left,
+                (0, false) => Side::Right,
+                (1, true) => Side::Bottom,
+                (1, false) => Side::Top,
+                (_, true) => Side::Front,
+                (_, false) => Side::Back,
+            };
+            return Some(RayHit { position: voxel, side, distance });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTER: Vec3 = Vec3::splat(0.5);
+
+    #[test]
+    fn hits_walls_along_every_axis() {
+        let cases = [
+            (Vec3::X, Side::Left),
+            (Vec3::NEG_X, Side::Right),
+            (Vec3::Y, Side::Bottom),
+            (Vec3::NEG_Y, Side::Top),
+            (Vec3::Z, Side::Front),
+            (Vec3::NEG_Z, Side::Back),
+        ];
+        for (direction, side) in cases {
+            let target = direction.as_ivec3() * 3;
+            let hit = raycast(CENTER, direction, 10.0, |voxel| voxel == target).unwrap();
+            assert_eq!(hit.position, target, "ray along {direction}");
+            assert_eq!(hit.side, side, "ray along {direction}");
+            assert_eq!(hit.distance, 2.5, "ray along {direction}");
+            assert_eq!(hit.adjacent(), direction.as_ivec3() * 2, "ray along {direction}");
+        }
+    }
+
+    #[test]
+    fn walks_connected_voxels_in_negative_directions() {
+        let direction = Vec3::new(-1.0, -0.5, -0.25);
+        let mut visited = vec![IVec3::ZERO];
+        let hit = raycast(CENTER, direction, 20.0, |voxel| {
+            visited.push(voxel);
+            voxel.x == -3
+        }).unwrap();
+
+        // Enters x = -3 at x = -2, after moving 2.5 along x
+        assert_eq!(hit.position, IVec3::new(-3, -1, -1));
+        assert_eq!(hit.side, Side::Right);
+        assert!((hit.distance - 2.5 * direction.length()).abs() < 1e-5);
+        for pair in visited.windows(2) {
+            let step = pair[1] - pair[0];
+            assert_eq!(step.x.abs() + step.y.abs() + step.z.abs(), 1, "{} to {}", pair[0], pair[1]);
+            assert!(step.max_element() <= 0, "{} to {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn skips_the_origin_voxel() {
+        let origin = Vec3::new(-0.5, 4.2, 7.9);
+        let hit = raycast(origin, Vec3::new(0.3, 1.0, 0.0), 5.0, |_| true).unwrap();
+        assert_eq!(hit.position, IVec3::new(-1, 5, 7));
+        assert_eq!(hit.side, Side::Bottom);
+    }
+
+    #[test]
+    fn stops_at_max_distance() {
+        let target = IVec3::new(0, 0, 3);
+        let mut checked = Vec::new();
+        let miss = raycast(CENTER, Vec3::Z, 2.4, |voxel| {
+            checked.push(voxel);
+            voxel == target
+        });
+        assert_eq!(miss, None);
+        assert_eq!(checked, vec![IVec3::new(0, 0, 1), IVec3::new(0, 0, 2)]);
+
+        assert!(raycast(CENTER, Vec3::Z, 2.5, |voxel| voxel == target).is_some());
+    }
+
+    #[test]
+    fn zero_direction_hits_nothing() {
+        assert_eq!(raycast(CENTER, Vec3::ZERO, 10.0, |_| true), None);
+    }
+}
@@ -0,0 +1,426 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use bevy::prelude::*;
+use bevy::utils::hashbrown::{HashMap, HashSet};
+use super::{Chunk, ChunkMut, MyChunk, CHUNK_LEN};
+use super::block::Block;
+
+/// Chunks along x and z stored in one region file, all heights included
+pub const REGION_SIDE: i32 = 32;
+
+const REGION_MAGIC: &[u8; 4] = b"BCRG";
+const REGION_VERSION: u8 = 1;
+
+#[derive(Debug)]
+pub enum RegionError {
+    Io(io::Error),
+    /// File or chunk data does not follow the format
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "region io error: {error}"),
+            Self::Corrupt(reason) => write!(f, "corrupt region data: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for RegionError {}
+
+impl From<io::Error> for RegionError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// Reads little endian numbers from a byte slice
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], RegionError> {
+        if self.0.len() < len {
+            return Err(RegionError::Corrupt("unexpected end of data"));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, RegionError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, RegionError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, RegionError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, RegionError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// Encodes voxels as a palette of blocks followed by
+/// runs of equal palette indices
+pub fn encode_chunk(chunk: &MyChunk) -> Vec<u8> {
+    let mut palette: Vec<Block> = Vec::new();
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for block in chunk.voxels.iter() {
+        let index = match palette.iter().position(|known| *known == block) {
+            Some(index) => index,
+            None => {
+                palette.push(block);
+                palette.len() - 1
+            }
+        } as u16;
+        match runs.last_mut() {
+            Some((len, last)) if *last == index => *len += 1,
+            _ => runs.push((1, index)),
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(2 + palette.len() * 2 + 4 + runs.len() * 4);
+    bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for block in palette {
+        bytes.extend_from_slice(&block.0.to_le_bytes());
+    }
+    bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    for (len, index) in runs {
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(&index.to_le_bytes());
+    }
+    bytes
+}
+
+/// Inverse of [`encode_chunk`]
+pub fn decode_chunk(position: IVec3, bytes: &[u8]) -> Result<MyChunk, RegionError> {
+    let mut reader = Reader(bytes);
+
+    let palette_len = reader.u16()?;
+    let palette = (0..palette_len)
+        .map(|_| reader.u16().map(Block))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut chunk = MyChunk::default().with_position(position);
+    let mut filled = 0;
+    for _ in 0..reader.u32()? {
+        let len = reader.u16()? as usize;
+        let block = *palette.get(reader.u16()? as usize)
+            .ok_or(RegionError::Corrupt("palette index out of range"))?;
+        if filled + len > CHUNK_LEN {
+            return Err(RegionError::Corrupt("too many voxels"));
+        }
+        if len == CHUNK_LEN {
+            chunk.fill(block);
+        } else {
+            for i in filled..filled + len {
+                let (x, y, z) = MyChunk::delinearize(i);
+                chunk.set(IVec3::new(x as i32, y as i32, z as i32), block);
+            }
+        }
+        filled += len;
+    }
+    if filled != CHUNK_LEN {
+        return Err(RegionError::Corrupt("too few voxels"));
+    }
+    Ok(chunk)
+}
+
+/// Encoded chunks of one region
+type Region = HashMap<IVec3, Vec<u8>>;
+
+fn read_region(path: &Path) -> Result<Region, RegionError> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Region::new()),
+        Err(error) => return Err(error.into()),
+    };
+    let mut reader = Reader(&bytes);
+    if reader.take(4)? != REGION_MAGIC {
+        return Err(RegionError::Corrupt("not a region file"));
+    }
+    if reader.u8()? != REGION_VERSION {
+        return Err(RegionError::Corrupt("unknown region version"));
+    }
+    let mut region = Region::new();
+    for _ in 0..reader.u32()? {
+        let position = IVec3::new(reader.i32()?, reader.i32()?, reader.i32()?);
+        let len = reader.u32()? as usize;
+        region.insert(position, reader.take(len)?.to_vec());
+    }
+    Ok(region)
+}
+
+fn encode_region(region: &Region) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(REGION_MAGIC);
+    bytes.push(REGION_VERSION);
+    bytes.extend_from_slice(&(region.len() as u32).to_le_bytes());
+    for (position, data) in region {
+        for value in position.to_array() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+    }
+    bytes
+}
+
+fn write_region(path: &Path, bytes: Vec<u8>) -> Result<(), RegionError> {
+    // Write next to the old file first, so a crash does not corrupt it
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, bytes)?;
+    fs::rename(temp, path)?;
+    Ok(())
+}
+
+/// Regions read so far
+#[derive(Default)]
+struct RegionCache {
+    regions: HashMap<IVec2, Region>,
+    /// Bumped whenever regions are dropped, so a file read from before that is not cached
+    evictions: u64,
+}
+
+/// Chunks saved in region files inside a directory.
+/// Regions are cached until they are written by [`RegionStorage::flush`],
+/// stored chunks are only written to disk there
+pub struct RegionStorage {
+    dir: PathBuf,
+    cache: Mutex<RegionCache>,
+    /// Regions changed since they were last written, only changed with `cache` locked
+    dirty: Mutex<HashSet<IVec2>>,
+    /// Held while writing, so an older copy of a region never overwrites a newer one
+    writing: Mutex<()>,
+}
+
+impl RegionStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            cache: Mutex::default(),
+            dirty: Mutex::default(),
+            writing: Mutex::default(),
+        }
+    }
+
+    fn region_of(position: IVec3) -> IVec2 {
+        IVec2::new(position.x, position.z).div_euclid(IVec2::splat(REGION_SIDE))
+    }
+
+    fn region_path(&self, region: IVec2) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.region", region.x, region.y))
+    }
+
+    fn with_region<R>(
+        &self,
+        region: IVec2,
+        f: impl FnOnce(&mut Region) -> R
+    ) -> Result<R, RegionError> {
+        loop {
+            let evictions = {
+                let mut cache = self.cache.lock().unwrap();
+                if let Some(data) = cache.regions.get_mut(&region) {
+                    return Ok(f(data));
+                }
+                cache.evictions
+            };
+            // Reading files with the cache locked would stall every other chunk
+            let loaded = read_region(&self.region_path(region))?;
+
+            let mut cache = self.cache.lock().unwrap();
+            if cache.evictions == evictions || cache.regions.contains_key(&region) {
+                // Stored while reading, the cached copy is newer
+                return Ok(f(cache.regions.entry(region).or_insert(loaded)));
+            }
+            // The file may have been written while reading it
+        }
+    }
+
+    /// Saved chunk at `position`, if there is one
+    pub fn load(&self, position: IVec3) -> Result<Option<MyChunk>, RegionError> {
+        let bytes = self.with_region(Self::region_of(position), |region| {
+            region.get(&position).cloned()
+        })?;
+        bytes.map(|bytes| decode_chunk(position, &bytes)).transpose()
+    }
+
+    /// Puts chunks into their cached regions, so they are loaded from there
+    /// even before the region files are written
+    pub fn store<'a>(&self, chunks: impl IntoIterator<Item = &'a MyChunk>) -> Result<(), RegionError> {
+        for chunk in chunks {
+            let region = Self::region_of(chunk.position);
+            self.with_region(region, |data| {
+                data.insert(chunk.position, encode_chunk(chunk));
+                // Before the cache is unlocked, so the region is not evicted
+                self.dirty.lock().unwrap().insert(region);
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Writes every region file changed since the last flush
+    pub fn flush(&self) -> Result<(), RegionError> {
+        let _writing = self.writing.lock().unwrap();
+        let dirty: Vec<IVec2> = self.dirty.lock().unwrap().drain().collect();
+        if dirty.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+        for (i, region) in dirty.iter().enumerate() {
+            let bytes = self.with_region(*region, |data| encode_region(data))?;
+            if let Err(error) = write_region(&self.region_path(*region), bytes) {
+                // Retry on the next flush
+                self.dirty.lock().unwrap().extend(&dirty[i..]);
+                return Err(error);
+            }
+        }
+
+        // Written regions are read again when needed
+        let mut cache = self.cache.lock().unwrap();
+        let dirty = self.dirty.lock().unwrap();
+        cache.regions.retain(|region, _| dirty.contains(region));
+        cache.evictions += 1;
+        Ok(())
+    }
+
+    /// Stores chunks and writes them right away
+    pub fn save<'a>(&self, chunks: impl IntoIterator<Item = &'a MyChunk>) -> Result<(), RegionError> {
+        self.store(chunks)?;
+        self.flush()
+    }
+}
+
+/// Where chunks are saved, cheap to clone into loading tasks
+#[derive(Resource, Clone)]
+pub struct ChunkStorage(pub Arc<RegionStorage>);
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use super::*;
+    use super::super::{Chunk, ChunkMut};
+
+    fn random_chunk(position: IVec3, seed: u64) -> MyChunk {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut chunk = MyChunk::default().with_position(position);
+        for i in 0..CHUNK_LEN {
+            let (x, y, z) = MyChunk::delinearize(i);
+            chunk.set(IVec3::new(x as i32, y as i32, z as i32), Block(rng.gen_range(0..12)));
+        }
+        chunk
+    }
+
+    /// Decodes and encodes again, the bytes must not change
+    fn assert_round_trip(chunk: &MyChunk) {
+        let bytes = encode_chunk(chunk);
+        let decoded = decode_chunk(chunk.position, &bytes).unwrap();
+        assert!(decoded.voxels == chunk.voxels);
+        assert_eq!(encode_chunk(&decoded), bytes);
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bevycraft-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn air_chunk_round_trip() {
+        assert_round_trip(&MyChunk::default());
+    }
+
+    #[test]
+    fn random_chunk_round_trip() {
+        assert_round_trip(&random_chunk(IVec3::new(-3, 1, 7), 42));
+    }
+
+    #[test]
+    fn uniform_chunk_is_one_run() {
+        // A run can not be longer than a chunk, so its length always fits
+        assert!(CHUNK_LEN <= u16::MAX as usize);
+
+        let mut chunk = MyChunk::default();
+        chunk.fill(Block::STONE);
+        assert_round_trip(&chunk);
+        // Palette of one block, then one run
+        assert_eq!(encode_chunk(&chunk).len(), 2 + 2 + 4 + 4);
+    }
+
+    #[test]
+    fn corrupt_chunk_is_rejected() {
+        let mut bytes = encode_chunk(&MyChunk::default());
+        bytes.truncate(bytes.len() - 1);
+        assert!(decode_chunk(IVec3::ZERO, &bytes).is_err());
+    }
+
+    #[test]
+    fn region_round_trip() {
+        let dir = temp_dir("region");
+        let chunks = [
+            random_chunk(IVec3::new(0, 0, 0), 1),
+            random_chunk(IVec3::new(31, 3, 5), 2),
+            random_chunk(IVec3::new(-1, 2, -40), 3),
+        ];
+        RegionStorage::new(&dir).save(chunks.iter()).unwrap();
+
+        // Fresh storage, so regions are read from the files
+        let storage = RegionStorage::new(&dir);
+        for chunk in chunks.iter() {
+            let loaded = storage.load(chunk.position).unwrap().unwrap();
+            assert!(loaded.voxels == chunk.voxels);
+            assert_eq!(loaded.get(IVec3::ONE), chunk.get(IVec3::ONE));
+        }
+        assert!(storage.load(IVec3::new(1, 0, 0)).unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stored_chunks_load_before_flush() {
+        let dir = temp_dir("store");
+        let storage = RegionStorage::new(&dir);
+        let chunk = random_chunk(IVec3::new(2, 0, -2), 4);
+        storage.store([&chunk]).unwrap();
+        assert!(storage.load(chunk.position).unwrap().unwrap().voxels == chunk.voxels);
+        assert!(!dir.exists());
+
+        storage.flush().unwrap();
+        let loaded = RegionStorage::new(&dir).load(chunk.position).unwrap().unwrap();
+        assert!(loaded.voxels == chunk.voxels);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn flushed_regions_are_evicted() {
+        let dir = temp_dir("evict");
+        let storage = RegionStorage::new(&dir);
+        let chunks = [random_chunk(IVec3::new(0, 0, 0), 5), random_chunk(IVec3::new(40, 1, 0), 6)];
+        storage.save(chunks.iter()).unwrap();
+        assert!(storage.cache.lock().unwrap().regions.is_empty());
+
+        // Read back from the files and cached again
+        for chunk in chunks.iter() {
+            assert!(storage.load(chunk.position).unwrap().unwrap().voxels == chunk.voxels);
+        }
+        assert_eq!(storage.cache.lock().unwrap().regions.len(), 2);
+
+        // Stored regions are kept until they are written
+        let changed = random_chunk(IVec3::new(1, 0, 0), 7);
+        storage.store([&changed]).unwrap();
+        assert_eq!(storage.cache.lock().unwrap().regions.len(), 2);
+        storage.flush().unwrap();
+        assert!(storage.cache.lock().unwrap().regions.is_empty());
+        assert!(storage.load(changed.position).unwrap().unwrap().voxels == changed.voxels);
+        assert!(storage.load(chunks[0].position).unwrap().unwrap().voxels == chunks[0].voxels);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
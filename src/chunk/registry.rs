@@ -0,0 +1,266 @@
+use std::fmt;
+use std::sync::Arc;
+use bevy::prelude::*;
+use bevy::asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext};
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use super::block::{Block, BlockInfo, Visibility};
+use super::face::Side;
+use super::texture::TextureLayers;
+
+const REGISTRY_PATH: &str = "blocks.ron";
+
+/// Texture names of block sides, more specific fields win over `all`
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct FaceTextures {
+    pub all: Option<String>,
+    pub top: Option<String>,
+    pub bottom: Option<String>,
+    pub side: Option<String>,
+}
+
+impl FaceTextures {
+    pub fn get(&self, side: Side) -> Option<&str> {
+        let specific = match side {
+            Side::Top => &self.top,
+            Side::Bottom => &self.bottom,
+            _ => &self.side,
+        };
+        specific.as_ref().or(self.all.as_ref()).map(String::as_str)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        [&self.all, &self.top, &self.bottom, &self.side]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+    }
+}
+
+/// Description of a block, as written in `assets/blocks.ron`
+#[derive(Deserialize, Clone, Debug)]
+pub struct BlockDef {
+    pub id: u16,
+    pub name: String,
+    #[serde(default = "BlockDef::default_visibility")]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub textures: FaceTextures,
+    #[serde(default)]
+    pub overlay: FaceTextures,
+    #[serde(default = "BlockDef::default_solid")]
+    pub is_solid: bool,
+    #[serde(default)]
+    pub is_liquid: bool,
+    /// Lets light through
+    #[serde(default)]
+    pub is_translucent: bool,
+    // Not used yet
+    #[allow(unused)]
+    #[serde(default)]
+    pub can_burn: bool,
+    /// Light level emitted by the block, from 0 to 15
+    #[serde(default)]
+    pub light_emission: u8,
+    /// Seconds it takes to break the block in survival
+    #[serde(default = "BlockDef::default_hardness")]
+    pub hardness: f32,
+}
+
+impl BlockDef {
+    fn default_visibility() -> Visibility {
+        Visibility::Opaque
+    }
+
+    fn default_solid() -> bool {
+        true
+    }
+
+    fn default_hardness() -> f32 {
+        1.0
+    }
+}
+
+#[derive(Asset, TypePath, Deserialize, Debug)]
+pub struct BlockRegistryAsset {
+    pub blocks: Vec<BlockDef>,
+}
+
+#[derive(Debug)]
+pub enum BlockRegistryLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for BlockRegistryLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "could not read block registry: {error}"),
+            Self::Ron(error) => write!(f, "could not parse block registry: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for BlockRegistryLoaderError {}
+
+impl From<std::io::Error> for BlockRegistryLoaderError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ron::error::SpannedError> for BlockRegistryLoaderError {
+    fn from(value: ron::error::SpannedError) -> Self {
+        Self::Ron(value)
+    }
+}
+
+#[derive(Default)]
+struct BlockRegistryLoader;
+
+impl AssetLoader for BlockRegistryLoader {
+    type Asset = BlockRegistryAsset;
+    type Settings = ();
+    type Error = BlockRegistryLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+/// Every known block, indexed by [`Block`] id.
+/// Replaced as a whole when `assets/blocks.ron` changes
+#[derive(Resource, Default)]
+pub struct BlockRegistry {
+    blocks: Vec<Option<BlockDef>>,
+}
+
+impl BlockRegistry {
+    pub fn new(defs: &[BlockDef]) -> Self {
+        let mut registry = Self::default();
+        for def in defs {
+            let index = def.id as usize;
+            if registry.blocks.len() <= index {
+                registry.blocks.resize(index + 1, None);
+            }
+            if let Some(existing) = &registry.blocks[index] {
+                warn!("block {} has the same id {} as {}, skipping it", def.name, def.id, existing.name);
+                continue;
+            }
+            registry.blocks[index] = Some(def.clone());
+        }
+        if registry.get(Block::AIR).is_none_or(|air| air.visibility != Visibility::Empty) {
+            warn!("block with id 0 should be an empty air block");
+        }
+        registry
+    }
+
+    pub fn get(&self, block: Block) -> Option<&BlockDef> {
+        self.blocks.get(block.0 as usize)?.as_ref()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Block, &BlockDef)> {
+        self.blocks.iter()
+            .flatten()
+            .map(|def| (Block(def.id), def))
+    }
+}
+
+/// [`BlockRegistry`] resolved against [`TextureLayers`],
+/// cheap to clone into meshing tasks
+#[derive(Resource, Clone, Default)]
+pub struct BlockInfos(Arc<Vec<BlockInfo>>);
+
+impl BlockInfos {
+    /// Unknown blocks are treated as air
+    pub fn get(&self, block: Block) -> BlockInfo {
+        self.0.get(block.0 as usize).copied().unwrap_or_default()
+    }
+
+    /// Infos indexed by block id, without going through the registry
+    #[cfg(test)]
+    pub fn new(infos: Vec<BlockInfo>) -> Self {
+        Self(Arc::new(infos))
+    }
+}
+
+#[derive(Resource)]
+struct BlockRegistryHandle(Handle<BlockRegistryAsset>);
+
+pub struct BlockRegistryPlugin;
+
+impl Plugin for BlockRegistryPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_asset::<BlockRegistryAsset>()
+            .init_asset_loader::<BlockRegistryLoader>()
+            .add_systems(Startup, load_block_registry)
+            .add_systems(Update, (
+                update_block_registry,
+                bake_block_infos.run_if(resource_exists_and_changed::<TextureLayers>)
+            ));
+    }
+}
+
+fn load_block_registry(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>
+) {
+    commands.insert_resource(BlockRegistryHandle(asset_server.load(REGISTRY_PATH)));
+}
+
+/// Replaces [`BlockRegistry`] on first load and on every hot reload
+fn update_block_registry(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<BlockRegistryAsset>>,
+    assets: Res<Assets<BlockRegistryAsset>>,
+    handle: Res<BlockRegistryHandle>
+) {
+    for event in events.read() {
+        let (AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id }) = event else {
+            continue;
+        };
+        if *id != handle.0.id() {
+            continue;
+        }
+        if let Some(asset) = assets.get(*id) {
+            info!("loaded {} blocks", asset.blocks.len());
+            commands.insert_resource(BlockRegistry::new(&asset.blocks));
+        }
+    }
+}
+
+fn bake_block_infos(
+    mut commands: Commands,
+    registry: Res<BlockRegistry>,
+    layers: Res<TextureLayers>
+) {
+    let layer = |name: Option<&str>| name.and_then(|name| layers.get(name));
+    let infos = registry.blocks.iter()
+        .enumerate()
+        .map(|(id, def)| match def {
+            Some(def) => BlockInfo {
+                block: Block(id as u16),
+                visibility: def.visibility,
+                textures: Side::ALL.map(|side| layer(def.textures.get(side))),
+                overlays: Side::ALL.map(|side| layer(def.overlay.get(side))),
+            },
+            None => BlockInfo::default(),
+        })
+        .collect();
+    commands.insert_resource(BlockInfos(Arc::new(infos)));
+}
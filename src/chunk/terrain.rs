@@ -0,0 +1,259 @@
+use std::sync::Arc;
+use bevy::prelude::*;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use super::{Chunk, ChunkMut, MyChunk, CHUNK_LEN, CHUNK_SIDE};
+use super::block::Block;
+use super::biome::{Biome, BiomeMap};
+use super::decoration::{self, TREE_SALT};
+
+/// Surface height around which terrain oscillates
+const BASE_HEIGHT: f64 = 32.0;
+/// Air at or below this height is filled with water
+const SEA_LEVEL: i32 = 30;
+/// Thickness of filler blocks under the surface
+const FILLER_DEPTH: i32 = 3;
+/// Cave noise above this value is carved out
+const CAVE_THRESHOLD: f64 = 0.35;
+
+/// Fills chunks with blocks,
+/// the same chunk position must always give the same chunk
+pub trait TerrainGenerator: Send + Sync + 'static {
+    /// Base terrain of a chunk
+    fn generate(&self, position: IVec3) -> MyChunk;
+    /// Places features like trees and ores on a chunk after [`Self::generate`],
+    /// including the parts of features that start in neighboring chunks
+    fn decorate(&self, _chunk: &mut MyChunk) {}
+    /// Biome of the column at world coordinates
+    fn biome(&self, x: i32, z: i32) -> Biome;
+}
+
+/// Seed of the world, [`WorldGenerator`] is rebuilt when it changes
+#[derive(Resource, Reflect, Clone, Copy, PartialEq, Eq, Debug)]
+#[reflect(Resource)]
+pub struct WorldSeed(pub u32);
+
+impl Default for WorldSeed {
+    fn default() -> Self {
+        Self(rand::random())
+    }
+}
+
+/// Generator used for new chunks, cheap to clone into generation tasks
+#[derive(Resource, Clone)]
+pub struct WorldGenerator(pub Arc<dyn TerrainGenerator>);
+
+/// Hills from 2d fractal noise shaped by biomes,
+/// with caves carved by 3d noise
+pub struct NoiseGenerator {
+    seed: u32,
+    surface: Fbm<Perlin>,
+    caves: Fbm<Perlin>,
+    biomes: BiomeMap,
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            surface: Fbm::<Perlin>::new(seed)
+                .set_octaves(4)
+                .set_frequency(1.0 / 128.0),
+            caves: Fbm::<Perlin>::new(seed.wrapping_add(1))
+                .set_octaves(2)
+                .set_frequency(1.0 / 32.0),
+            biomes: BiomeMap::new(seed),
+        }
+    }
+
+    /// Height of the topmost block of a column
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        let noise = self.surface.get([x as f64, z as f64]);
+        (BASE_HEIGHT + noise * self.biomes.height_scale(x, z)).round() as i32
+    }
+
+    fn is_cave(&self, pos: IVec3) -> bool {
+        // Keep the bottom layer solid
+        pos.y > 0 && self.caves.get(pos.as_dvec3().to_array()) > CAVE_THRESHOLD
+    }
+
+    /// Undecorated block at `pos` in a column of the given height and biome
+    fn terrain_block(&self, pos: IVec3, height: i32, biome: Biome) -> Block {
+        // Shores and sea floor
+        let underwater = height <= SEA_LEVEL + 1 && biome != Biome::Snowy;
+
+        if pos.y > height {
+            if pos.y <= SEA_LEVEL { Block::WATER } else { Block::AIR }
+        } else if self.is_cave(pos) && !(underwater && pos.y > height - FILLER_DEPTH) {
+            // Caves must not drain the sea
+            Block::AIR
+        } else if pos.y == height {
+            if underwater { Block::SAND } else { biome.surface() }
+        } else if pos.y > height - FILLER_DEPTH {
+            if underwater { Block::SAND } else { biome.filler() }
+        } else {
+            Block::STONE
+        }
+    }
+
+    /// Root and trunk height of the tree growing on a column, if there is one.
+    /// Only depends on the column, so neighboring chunks agree on it
+    fn tree_at(&self, x: i32, z: i32) -> Option<(IVec3, i32)> {
+        let height = self.height(x, z);
+        if height <= SEA_LEVEL {
+            return None;
+        }
+        let biome = self.biomes.biome(x, z);
+        let ground = IVec3::new(x, height, z);
+        if !matches!(self.terrain_block(ground, height, biome), Block::GRASS | Block::SNOW) {
+            return None;
+        }
+        let root = ground + IVec3::Y;
+        let h = decoration::hash(self.seed, root, TREE_SALT);
+        (decoration::chance(h) < biome.decorations().tree_chance)
+            .then_some((root, 4 + (h % 3) as i32))
+    }
+}
+
+impl TerrainGenerator for NoiseGenerator {
+    fn generate(&self, position: IVec3) -> MyChunk {
+        let mut chunk = MyChunk::default().with_position(position);
+        let origin = position * CHUNK_SIDE as i32;
+
+        let mut columns = [(0, Biome::Plains); CHUNK_SIDE * CHUNK_SIDE];
+        for (i, column) in columns.iter_mut().enumerate() {
+            let (x, z) = (origin.x + (i % CHUNK_SIDE) as i32, origin.z + (i / CHUNK_SIDE) as i32);
+            *column = (self.height(x, z), self.biomes.biome(x, z));
+        }
+
+        for i in 0..CHUNK_LEN {
+            let (x, y, z) = MyChunk::delinearize(i);
+            let local = IVec3::new(x as i32, y as i32, z as i32);
+            let pos = origin + local;
+            let (height, biome) = columns[x + z * CHUNK_SIDE];
+            chunk.set(local, self.terrain_block(pos, height, biome));
+        }
+        chunk
+    }
+
+    fn decorate(&self, chunk: &mut MyChunk) {
+        let side = CHUNK_SIDE as i32;
+        let origin = chunk.position * side;
+        let mut writes = Vec::new();
+
+        // Trees are at most 5 blocks wide, so roots up to 2 blocks away reach this chunk
+        let reach = decoration::TREE_RADIUS;
+        for x in origin.x - reach..origin.x + side + reach {
+            for z in origin.z - reach..origin.z + side + reach {
+                let Some((root, height)) = self.tree_at(x, z) else {
+                    continue;
+                };
+                let top = root.y + decoration::tree_top(height);
+                if top >= origin.y && root.y < origin.y + side {
+                    writes.extend(decoration::tree(root, height));
+                }
+            }
+        }
+
+        // Veins wander less than their length away from their start
+        let reach = decoration::ORE_VEIN_LENGTH as i32;
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let starts = decoration::ore_veins(self.seed, chunk.position + IVec3::new(x, y, z));
+                    for start in starts {
+                        let offset = start - origin;
+                        if offset.min_element() > -reach && offset.max_element() < side + reach {
+                            writes.extend(decoration::ore_vein(self.seed, start));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Blocks outside of this chunk are placed when its neighbors are decorated
+        decoration::apply_writes(chunk, writes);
+    }
+
+    fn biome(&self, x: i32, z: i32) -> Biome {
+        self.biomes.biome(x, z)
+    }
+}
+
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<WorldSeed>()
+            .register_type::<WorldSeed>()
+            .add_systems(PreUpdate, update_world_generator.run_if(resource_changed::<WorldSeed>));
+    }
+}
+
+fn update_world_generator(
+    mut commands: Commands,
+    seed: Res<WorldSeed>
+) {
+    info!("generating world with seed {}", seed.0);
+    commands.insert_resource(WorldGenerator(Arc::new(NoiseGenerator::new(seed.0))));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [IVec3; 5] = [
+        IVec3::new(0, 1, 0),
+        IVec3::new(0, 2, 0),
+        IVec3::new(-1, 2, -1),
+        IVec3::new(-7, 1, 12),
+        IVec3::new(25, 2, -40),
+    ];
+
+    #[test]
+    fn same_seed_gives_same_chunks() {
+        let (first, second) = (NoiseGenerator::new(1234), NoiseGenerator::new(1234));
+        for pos in POSITIONS {
+            let (mut a, mut b) = (first.generate(pos), second.generate(pos));
+            assert!(a.voxels == b.voxels, "chunk at {pos} differs");
+
+            first.decorate(&mut a);
+            second.decorate(&mut b);
+            assert!(a.voxels == b.voxels, "decorated chunk at {pos} differs");
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_chunks() {
+        let (first, second) = (NoiseGenerator::new(1234), NoiseGenerator::new(4321));
+        let same = POSITIONS.iter()
+            .all(|pos| first.generate(*pos).voxels == second.generate(*pos).voxels);
+        assert!(!same);
+    }
+
+    #[test]
+    fn trees_cross_chunk_borders() {
+        let generator = NoiseGenerator::new(1234);
+        let side = CHUNK_SIDE as i32;
+        // A tree on the last column of a chunk, with its leaves in the next one
+        let (root, height) = (0..64)
+            .flat_map(|chunk_x| (0..64).map(move |z| (chunk_x * side + side - 1, z)))
+            .filter_map(|(x, z)| generator.tree_at(x, z))
+            // Within one chunk vertically
+            .find(|(root, height)| root.y.rem_euclid(side) + decoration::tree_top(*height) < side)
+            .expect("no tree on a chunk border");
+        let neighbor = (root + IVec3::X).div_euclid(IVec3::splat(side));
+
+        let mut chunk = generator.generate(neighbor);
+        let undecorated = chunk.clone();
+        generator.decorate(&mut chunk);
+        let placed = decoration::tree(root, height).into_iter()
+            .filter(|(pos, _)| pos.div_euclid(IVec3::splat(side)) == neighbor)
+            .filter(|(pos, block)| {
+                let local = *pos - neighbor * side;
+                undecorated.get(local) == Block::AIR && chunk.get(local) == *block
+            })
+            .count();
+        assert!(placed > 0, "tree at {root} is cut off at the chunk border");
+    }
+}
@@ -0,0 +1,279 @@
+use std::fmt;
+use bevy::prelude::*;
+use bevy::asset::LoadState;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{
+    Extent3d,
+    TextureDimension,
+    TextureViewDescriptor,
+    TextureViewDimension
+};
+use bevy::utils::hashbrown::HashMap;
+use super::registry::BlockRegistry;
+
+/// Texture array with one layer per block texture, see [`TextureLayers`]
+#[derive(Resource)]
+pub struct BlockTextureArray(pub Handle<Image>);
+
+/// Texture array layer of every block texture
+#[derive(Resource, Clone, Default)]
+pub struct TextureLayers(HashMap<String, u32>);
+
+impl TextureLayers {
+    pub fn get(&self, texture: &str) -> Option<u32> {
+        self.0.get(texture).copied()
+    }
+}
+
+/// Block textures that are still loading, in layer order
+#[derive(Resource)]
+struct PendingTextures(Vec<(String, Handle<Image>)>);
+
+#[derive(Debug)]
+pub enum TextureArrayError {
+    /// No block has a texture
+    NoTextures,
+    /// Images that could not be loaded
+    LoadFailed(Vec<String>),
+    /// Images whose size differs from the first one
+    SizeMismatch {
+        expected: UVec2,
+        mismatched: Vec<(String, UVec2)>
+    },
+    /// Images whose pixel format differs from the first one
+    FormatMismatch(Vec<String>),
+}
+
+impl fmt::Display for TextureArrayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoTextures => {
+                write!(f, "no block textures to build a texture array from")
+            },
+            Self::LoadFailed(names) => {
+                write!(f, "failed to load block textures: {}", names.join(", "))
+            },
+            Self::SizeMismatch { expected, mismatched } => {
+                write!(f, "block textures must all be {}x{}, but got", expected.x, expected.y)?;
+                for (i, (name, size)) in mismatched.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { "," };
+                    write!(f, "{separator} {name} ({}x{})", size.x, size.y)?;
+                }
+                Ok(())
+            },
+            Self::FormatMismatch(names) => {
+                write!(f, "block textures have different pixel formats: {}", names.join(", "))
+            },
+        }
+    }
+}
+
+impl std::error::Error for TextureArrayError {}
+
+pub struct TextureArrayPlugin;
+
+impl Plugin for TextureArrayPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Update, (
+                load_block_textures.run_if(resource_exists_and_changed::<BlockRegistry>),
+                build_texture_array.run_if(resource_exists::<PendingTextures>)
+            ).chain());
+    }
+}
+
+/// Starts loading every texture referenced by [`BlockRegistry`],
+/// the texture array is rebuilt whenever the registry changes
+fn load_block_textures(
+    mut commands: Commands,
+    registry: Res<BlockRegistry>,
+    asset_server: Res<AssetServer>
+) {
+    let names = registry.iter()
+        .flat_map(|(_, def)| def.textures.names().chain(def.overlay.names()));
+
+    let mut textures: Vec<(String, Handle<Image>)> = Vec::new();
+    for name in names {
+        if textures.iter().all(|(loaded, _)| loaded != name) {
+            textures.push((name.to_string(), asset_server.load(name.to_string())));
+        }
+    }
+    commands.insert_resource(PendingTextures(textures));
+}
+
+fn build_texture_array(
+    mut commands: Commands,
+    block_textures: Res<PendingTextures>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>
+) {
+    let failed: Vec<_> = block_textures.0.iter()
+        .filter(|(_, handle)| asset_server.load_state(handle) == LoadState::Failed)
+        .map(|(name, _)| name.clone())
+        .collect();
+    if !failed.is_empty() {
+        error!("{}", TextureArrayError::LoadFailed(failed));
+        commands.remove_resource::<PendingTextures>();
+        return;
+    }
+
+    let loaded = block_textures.0.iter()
+        .all(|(_, handle)| asset_server.is_loaded_with_dependencies(handle));
+    if !loaded {
+        return;
+    }
+
+    let textures: Vec<_> = block_textures.0.iter()
+        .map(|(name, handle)| (name.as_str(), images.get(handle).unwrap()))
+        .collect();
+
+    match stitch_texture_array(&textures) {
+        Ok((image, layers)) => {
+            commands.insert_resource(BlockTextureArray(images.add(image)));
+            commands.insert_resource(layers);
+        },
+        Err(error) => error!("{error}"),
+    }
+    commands.remove_resource::<PendingTextures>();
+}
+
+/// Stacks equally sized images into one image with a layer per image
+fn stitch_texture_array(
+    textures: &[(&str, &Image)]
+) -> Result<(Image, TextureLayers), TextureArrayError> {
+    let Some((_, first)) = textures.first() else {
+        return Err(TextureArrayError::NoTextures);
+    };
+    let expected = first.size();
+    let format = first.texture_descriptor.format;
+
+    let mismatched: Vec<_> = textures.iter()
+        .filter(|(_, image)| image.size() != expected)
+        .map(|(name, image)| (name.to_string(), image.size()))
+        .collect();
+    if !mismatched.is_empty() {
+        return Err(TextureArrayError::SizeMismatch { expected, mismatched });
+    }
+
+    let mismatched: Vec<_> = textures.iter()
+        .filter(|(_, image)| image.texture_descriptor.format != format)
+        .map(|(name, _)| name.to_string())
+        .collect();
+    if !mismatched.is_empty() {
+        return Err(TextureArrayError::FormatMismatch(mismatched));
+    }
+
+    let mut layers = HashMap::with_capacity(textures.len());
+    let mut data = Vec::with_capacity(first.data.len() * textures.len());
+    for (layer, (name, image)) in textures.iter().enumerate() {
+        data.extend_from_slice(&image.data);
+        layers.insert(name.to_string(), layer as u32);
+    }
+
+    let layer_count = textures.len() as u32;
+    let mut image = Image::new(
+        Extent3d {
+            width: expected.x,
+            height: expected.y * layer_count,
+            depth_or_array_layers: 1
+        },
+        TextureDimension::D2,
+        data,
+        format,
+        RenderAssetUsages::RENDER_WORLD
+    );
+    image.reinterpret_stacked_2d_as_array(layer_count);
+    // Otherwise an array of one layer is viewed as a plain 2d texture
+    image.texture_view_descriptor = Some(TextureViewDescriptor {
+        dimension: Some(TextureViewDimension::D2Array),
+        ..default()
+    });
+
+    Ok((image, TextureLayers(layers)))
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::render::render_resource::TextureFormat;
+    use super::*;
+
+    fn image(width: u32, height: u32, pixel: [u8; 4], format: TextureFormat) -> Image {
+        Image::new_fill(
+            Extent3d { width, height, depth_or_array_layers: 1 },
+            TextureDimension::D2,
+            &pixel,
+            format,
+            RenderAssetUsages::RENDER_WORLD
+        )
+    }
+
+    #[test]
+    fn stacks_textures_in_order() {
+        let format = TextureFormat::Rgba8UnormSrgb;
+        let images = [
+            image(2, 2, [1, 1, 1, 1], format),
+            image(2, 2, [2, 2, 2, 2], format),
+            image(2, 2, [3, 3, 3, 3], format),
+        ];
+        let names = ["dirt.png", "grass_top.png", "stone.png"];
+        let textures: Vec<_> = names.into_iter().zip(images.iter()).collect();
+
+        let (array, layers) = stitch_texture_array(&textures).unwrap();
+        assert_eq!(array.texture_descriptor.size.depth_or_array_layers, 3);
+        assert_eq!(array.size(), UVec2::new(2, 2));
+        for (layer, name) in names.into_iter().enumerate() {
+            assert_eq!(layers.get(name), Some(layer as u32));
+            // 2x2 pixels of 4 bytes each
+            let data = &array.data[layer * 16..(layer + 1) * 16];
+            assert!(data.iter().all(|byte| *byte == layer as u8 + 1), "layer of {name}");
+        }
+        assert_eq!(layers.get("missing.png"), None);
+    }
+
+    #[test]
+    fn lists_textures_of_another_size() {
+        let format = TextureFormat::Rgba8UnormSrgb;
+        let images = [
+            image(2, 2, [0; 4], format),
+            image(4, 2, [0; 4], format),
+            image(2, 2, [0; 4], format),
+            image(2, 4, [0; 4], format),
+        ];
+        let names = ["a.png", "wide.png", "b.png", "tall.png"];
+        let textures: Vec<_> = names.into_iter().zip(images.iter()).collect();
+
+        match stitch_texture_array(&textures) {
+            Err(TextureArrayError::SizeMismatch { expected, mismatched }) => {
+                assert_eq!(expected, UVec2::new(2, 2));
+                assert_eq!(mismatched, vec![
+                    ("wide.png".to_string(), UVec2::new(4, 2)),
+                    ("tall.png".to_string(), UVec2::new(2, 4)),
+                ]);
+            },
+            other => panic!("expected a size mismatch, got {:?}", other.map(|(_, layers)| layers.0)),
+        }
+    }
+
+    #[test]
+    fn lists_textures_of_another_format() {
+        let images = [
+            image(2, 2, [0; 4], TextureFormat::Rgba8UnormSrgb),
+            image(2, 2, [0; 4], TextureFormat::Rgba8Unorm),
+            image(2, 2, [0; 4], TextureFormat::Rgba8UnormSrgb),
+        ];
+        let names = ["a.png", "linear.png", "b.png"];
+        let textures: Vec<_> = names.into_iter().zip(images.iter()).collect();
+
+        match stitch_texture_array(&textures) {
+            Err(TextureArrayError::FormatMismatch(mismatched)) => {
+                assert_eq!(mismatched, vec!["linear.png".to_string()]);
+            },
+            other => panic!("expected a format mismatch, got {:?}", other.map(|(_, layers)| layers.0)),
+        }
+    }
+
+    #[test]
+    fn fails_without_textures() {
+        assert!(matches!(stitch_texture_array(&[]), Err(TextureArrayError::NoTextures)));
+    }
+}
@@ -0,0 +1,140 @@
+use bevy::prelude::*;
+use super::Chunk;
+use super::block::{Block, BlockInfo};
+use super::face::Side;
+use super::light::Light;
+use super::registry::BlockInfos;
+
+/// Chunk together with its 6 direct neighbors, indexed by [`Side`],
+/// so that faces on chunk borders can be culled.
+/// Neighbors sharing only an edge or a corner are reduced to the blocks touching the chunk,
+/// which is all ambient occlusion needs, see [`ChunkView::with_diagonals`].
+/// Neighbors that are not loaded are treated as empty.
+/// Blocks are resolved through [`BlockInfos`] for meshing
+pub struct ChunkView<C> {
+    center: C,
+    neighbors: [Option<C>; 6],
+    /// Indexed by [`diagonal_index`], empty for direct neighbors and missing chunks
+    diagonals: [Vec<Block>; 27],
+    infos: BlockInfos,
+}
+
+/// Index of the chunk at `offset` from the center, every component in -1..=1
+fn diagonal_index(offset: IVec3) -> usize {
+    (offset.x + 1 + (offset.y + 1) * 3 + (offset.z + 1) * 9) as usize
+}
+
+impl<C> ChunkView<C> {
+    pub fn new(center: C, neighbors: [Option<C>; 6], infos: BlockInfos) -> Self {
+        Self { center, neighbors, diagonals: Default::default(), infos }
+    }
+}
+
+impl<C> ChunkView<C>
+where
+    C: Chunk<Output = Block>
+{
+    /// Copies the blocks of edge and corner neighbors that touch the center chunk,
+    /// `neighbor` gives the chunk at an offset from the center
+    pub fn with_diagonals<'a>(mut self, neighbor: impl Fn(IVec3) -> Option<&'a C>) -> Self
+    where
+        C: 'a
+    {
+        let side = Self::SIDE as i32;
+        for i in 0..27 {
+            let offset = IVec3::new(i % 3, i / 3 % 3, i / 9) - 1;
+            if offset.cmpne(IVec3::ZERO).bitmask().count_ones() < 2 {
+                continue;
+            }
+            let Some(chunk) = neighbor(offset) else {
+                continue;
+            };
+            // Voxels on the faces turned to the center, along the shared edge if there is one
+            let touching = IVec3::select(offset.cmplt(IVec3::ZERO), IVec3::splat(side - 1), IVec3::ZERO);
+            self.diagonals[diagonal_index(offset)] = match (0..3).find(|axis| offset[*axis] == 0) {
+                Some(axis) => (0..side)
+                    .map(|along| {
+                        let mut pos = touching;
+                        pos[axis] = along;
+                        chunk.get(pos)
+                    })
+                    .collect(),
+                None => vec![chunk.get(touching)],
+            };
+        }
+        self
+    }
+
+    /// Block of an edge or corner neighbor, `None` for positions in other chunks
+    fn diagonal_block(&self, pos: IVec3) -> Option<Block> {
+        let side = Self::SIDE as i32;
+        let offset = pos.div_euclid(IVec3::splat(side));
+        let diagonal = offset.cmpne(IVec3::ZERO).bitmask().count_ones() >= 2;
+        if !diagonal || offset.abs().max_element() > 1 {
+            return None;
+        }
+        let local = pos.rem_euclid(IVec3::splat(side));
+        let touching = IVec3::select(offset.cmplt(IVec3::ZERO), IVec3::splat(side - 1), IVec3::ZERO);
+        let mut index = 0;
+        for axis in 0..3 {
+            if offset[axis] == 0 {
+                index = local[axis] as usize;
+            } else if local[axis] != touching[axis] {
+                // Only voxels touching the center chunk are kept
+                return Some(Block::AIR);
+            }
+        }
+        Some(self.diagonals[diagonal_index(offset)].get(index).copied().unwrap_or(Block::AIR))
+    }
+
+    /// Chunk containing `pos` and the position inside of it
+    fn locate(&self, pos: IVec3) -> Option<(&C, IVec3)> {
+        let side = Self::SIDE as i32;
+        let offset = pos.div_euclid(IVec3::splat(side));
+        let side_index = match offset.to_array() {
+            [0, 0, 0] => return Some((&self.center, pos)),
+            [-1, 0, 0] => Side::Left,
+            [1, 0, 0] => Side::Right,
+            [0, -1, 0] => Side::Bottom,
+            [0, 1, 0] => Side::Top,
+            [0, 0, -1] => Side::Front,
+            [0, 0, 1] => Side::Back,
+            _ => return None,
+        };
+        self.neighbors[usize::from(side_index)].as_ref()
+            .map(|neighbor| (neighbor, pos.rem_euclid(IVec3::splat(side))))
+    }
+
+    fn block(&self, pos: IVec3) -> Block {
+        if let Some(block) = self.diagonal_block(pos) {
+            return block;
+        }
+        self.locate(pos).map_or(Block::AIR, |(chunk, pos)| chunk.get(pos))
+    }
+}
+
+impl<C> Chunk for ChunkView<C>
+where
+    C: Chunk<Output = Block>
+{
+    type Output = BlockInfo;
+
+    const SIDE: usize = C::SIDE;
+
+    fn get(&self, pos: IVec3) -> Self::Output {
+        self.infos.get(self.block(pos))
+    }
+
+    /// Borders with chunks that are not loaded yet are lit like open sky
+    fn light(&self, pos: IVec3) -> Light {
+        self.locate(pos).map_or(Light::SKY, |(chunk, pos)| chunk.light(pos))
+    }
+
+    fn is_uniform(&self) -> Option<Self::Output> {
+        self.center.is_uniform().map(|block| self.infos.get(block))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.center.is_empty()
+    }
+}
@@ -0,0 +1,233 @@
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use super::{Chunk, ChunkMut, MyChunk, CHUNK_SIDE};
+use super::block::Block;
+use super::loader::{ChunkMap, ChunkModified, WORLD_HEIGHT};
+use super::light::LightUpdates;
+
+/// Lowest y above every chunk, there is only air from here on
+pub const WORLD_TOP: i32 = WORLD_HEIGHT * CHUNK_SIDE as i32;
+
+/// Position of the chunk containing a world voxel,
+/// and index of the voxel inside of it
+pub fn split_position(pos: IVec3) -> (IVec3, usize) {
+    let side = IVec3::splat(MyChunk::SIDE as i32);
+    let local = pos.rem_euclid(side);
+    let index = MyChunk::linearize(local.x as usize, local.y as usize, local.z as usize);
+    (pos.div_euclid(side), index)
+}
+
+/// Blocks of every loaded chunk in world coordinates.
+/// Positions in chunks that are not loaded yet have no block
+#[derive(SystemParam)]
+pub struct VoxelWorld<'w, 's> {
+    commands: Commands<'w, 's>,
+    chunk_map: Res<'w, ChunkMap>,
+    chunks: Query<'w, 's, &'static mut MyChunk>,
+    light_updates: ResMut<'w, LightUpdates>,
+}
+
+impl<'w, 's> VoxelWorld<'w, 's> {
+    fn chunk_entity(&self, chunk_pos: IVec3) -> Option<Entity> {
+        self.chunk_map.chunks.get(&chunk_pos).copied()
+    }
+
+    pub fn get_block(&self, pos: IVec3) -> Option<Block> {
+        let (chunk_pos, index) = split_position(pos);
+        let entity = self.chunk_entity(chunk_pos)?;
+        self.chunks.get(entity).ok().map(|chunk| chunk.voxels.get(index))
+    }
+
+    /// Replaces a block, the chunk is relit, remeshed and saved later.
+    /// Returns `false` if the chunk is not loaded
+    pub fn set_block(&mut self, pos: IVec3, block: Block) -> bool {
+        let (chunk_pos, index) = split_position(pos);
+        let Some(entity) = self.chunk_entity(chunk_pos) else {
+            return false;
+        };
+        let Ok(mut chunk) = self.chunks.get_mut(entity) else {
+            return false;
+        };
+        // Only real changes should trigger remeshing
+        if chunk.voxels.get(index) != block {
+            chunk.set(pos - chunk_pos * MyChunk::SIDE as i32, block);
+            self.commands.entity(entity).insert(ChunkModified);
+            self.light_updates.0.push(pos);
+        }
+        true
+    }
+
+    /// Sets every block between `min` and `max` inclusive,
+    /// returns how many of them were in loaded chunks
+    // Not used yet
+    #[allow(unused)]
+    pub fn fill_region(&mut self, min: IVec3, max: IVec3, block: Block) -> usize {
+        let (min, max) = (min.min(max), min.max(max));
+        let side = IVec3::splat(MyChunk::SIDE as i32);
+        let (min_chunk, max_chunk) = (min.div_euclid(side), max.div_euclid(side));
+
+        let mut filled = 0;
+        for cx in min_chunk.x..=max_chunk.x {
+            for cy in min_chunk.y..=max_chunk.y {
+                for cz in min_chunk.z..=max_chunk.z {
+                    let chunk_pos = IVec3::new(cx, cy, cz);
+                    let Some(entity) = self.chunk_entity(chunk_pos) else {
+                        continue;
+                    };
+                    let Ok(mut chunk) = self.chunks.get_mut(entity) else {
+                        continue;
+                    };
+                    // Part of the region inside of this chunk, in local coordinates
+                    let origin = chunk_pos * side;
+                    let from = (min - origin).max(IVec3::ZERO);
+                    let to = (max - origin).min(side - 1);
+
+                    let mut changed = false;
+                    let chunk_ref = chunk.bypass_change_detection();
+                    for x in from.x..=to.x {
+                        for y in from.y..=to.y {
+                            for z in from.z..=to.z {
+                                let local = IVec3::new(x, y, z);
+                                if chunk_ref.get(local) != block {
+                                    chunk_ref.set(local, block);
+                                    changed = true;
+                                    self.light_updates.0.push(origin + local);
+                                }
+                            }
+                        }
+                    }
+                    let size = to - from + 1;
+                    filled += (size.x * size.y * size.z) as usize;
+                    if changed {
+                        chunk.set_changed();
+                        self.commands.entity(entity).insert(ChunkModified);
+                    }
+                }
+            }
+        }
+        filled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+    use super::*;
+
+    #[test]
+    fn splits_positions_around_chunk_borders() {
+        let side = MyChunk::SIDE as i32;
+        // World coordinate, chunk coordinate and local coordinate on one axis
+        let cases = [
+            (-1, -1, side - 1),
+            (-16, -1, 0),
+            (-17, -2, side - 1),
+            (15, 0, side - 1),
+            (16, 1, 0),
+        ];
+        for axis in 0..3 {
+            for (world, chunk, local) in cases {
+                let mut pos = IVec3::splat(3);
+                pos[axis] = world;
+                let mut expected_chunk = IVec3::ZERO;
+                expected_chunk[axis] = chunk;
+                let mut expected_local = IVec3::splat(3);
+                expected_local[axis] = local;
+
+                let (chunk_pos, index) = split_position(pos);
+                assert_eq!(chunk_pos, expected_chunk, "chunk of {pos}");
+                let (x, y, z) = MyChunk::delinearize(index);
+                assert_eq!(IVec3::new(x as i32, y as i32, z as i32), expected_local, "voxel of {pos}");
+            }
+        }
+    }
+
+    #[test]
+    fn sets_blocks_at_negative_positions() {
+        let mut world = World::new();
+        let mut chunk_map = ChunkMap::default();
+        let positions = [IVec3::new(-1, 0, -1), IVec3::new(0, 0, -1), IVec3::new(-1, 0, 0)];
+        for position in positions {
+            let entity = world.spawn(MyChunk::default().with_position(position)).id();
+            chunk_map.chunks.insert(position, entity);
+        }
+        world.insert_resource(chunk_map);
+        world.init_resource::<LightUpdates>();
+
+        let pos = IVec3::new(-1, 5, -16);
+        let mut state = SystemState::<VoxelWorld>::new(&mut world);
+        let mut voxel_world = state.get_mut(&mut world);
+        assert_eq!(voxel_world.get_block(pos), Some(Block::AIR));
+        assert!(voxel_world.set_block(pos, Block::STONE));
+        assert_eq!(voxel_world.get_block(pos), Some(Block::STONE));
+        // Chunk below is not loaded
+        assert_eq!(voxel_world.get_block(pos - IVec3::Y * 6), None);
+        assert!(!voxel_world.set_block(pos - IVec3::Y * 6, Block::STONE));
+        state.apply(&mut world);
+
+        let chunk_map = world.resource::<ChunkMap>();
+        for position in positions {
+            let modified = world.get::<ChunkModified>(chunk_map.chunks[&position]).is_some();
+            assert_eq!(modified, position == IVec3::new(-1, 0, -1), "chunk at {position}");
+        }
+        let chunk = world.get::<MyChunk>(chunk_map.chunks[&IVec3::new(-1, 0, -1)]).unwrap();
+        assert_eq!(chunk.get(IVec3::new(15, 5, 0)), Block::STONE);
+        assert_eq!(world.resource::<LightUpdates>().0, vec![pos]);
+    }
+
+    #[test]
+    fn fills_regions_across_chunk_borders() {
+        let mut world = World::new();
+        let mut chunk_map = ChunkMap::default();
+        // Every chunk around the origin, but the one at the origin itself
+        let positions: Vec<IVec3> = (0..8)
+            .map(|i| IVec3::new(i & 1, (i >> 1) & 1, (i >> 2) & 1) - 1)
+            .collect();
+        for position in &positions[..7] {
+            let entity = world.spawn(MyChunk::default().with_position(*position)).id();
+            chunk_map.chunks.insert(*position, entity);
+        }
+        world.insert_resource(chunk_map);
+        world.init_resource::<LightUpdates>();
+
+        // 4x4x4 blocks, 8 of them in the missing chunk, corners can come in any order
+        let (from, to) = (IVec3::splat(1), IVec3::splat(-2));
+        let mut state = SystemState::<VoxelWorld>::new(&mut world);
+        let mut voxel_world = state.get_mut(&mut world);
+        assert_eq!(voxel_world.fill_region(from, to, Block::STONE), 56);
+        for x in -3..=2 {
+            for y in -3..=2 {
+                for z in -3..=2 {
+                    let pos = IVec3::new(x, y, z);
+                    let inside = pos.cmpge(to).all() && pos.cmple(from).all();
+                    let expected = if pos.cmpge(IVec3::ZERO).all() {
+                        None
+                    } else if inside {
+                        Some(Block::STONE)
+                    } else {
+                        Some(Block::AIR)
+                    };
+                    assert_eq!(voxel_world.get_block(pos), expected, "block at {pos}");
+                }
+            }
+        }
+        state.apply(&mut world);
+
+        let chunk_map = world.resource::<ChunkMap>();
+        for position in &positions[..7] {
+            assert!(world.get::<ChunkModified>(chunk_map.chunks[position]).is_some(), "chunk at {position}");
+        }
+        assert_eq!(world.resource::<LightUpdates>().0.len(), 56);
+
+        // Blocks that are already there are counted, but change nothing
+        for entity in chunk_map.chunks.values().copied().collect::<Vec<_>>() {
+            world.entity_mut(entity).remove::<ChunkModified>();
+        }
+        world.resource_mut::<LightUpdates>().0.clear();
+        let mut voxel_world = state.get_mut(&mut world);
+        assert_eq!(voxel_world.fill_region(to, from, Block::STONE), 56);
+        state.apply(&mut world);
+        assert!(world.query::<&ChunkModified>().iter(&world).next().is_none());
+        assert!(world.resource::<LightUpdates>().0.is_empty());
+    }
+}
@@ -11,6 +11,13 @@ pub struct KeyBindsResource {
     pub move_up: KeyCode,
     pub move_down: KeyCode,
     pub grab_cursor: KeyCode,
+    pub toggle_flight: KeyCode,
+    pub cycle_game_mode: KeyCode,
+    pub break_block: MouseButton,
+    pub place_block: MouseButton,
+    pub pick_block: MouseButton,
+    pub toggle_block_outline: KeyCode,
+    pub toggle_chunk_borders: KeyCode,
 }
 
 impl Default for KeyBindsResource {
@@ -23,6 +30,13 @@ impl Default for KeyBindsResource {
             move_up: KeyCode::Space,
             move_down: KeyCode::ShiftLeft,
             grab_cursor: KeyCode::Escape,
+            toggle_flight: KeyCode::KeyF,
+            cycle_game_mode: KeyCode::KeyM,
+            break_block: MouseButton::Left,
+            place_block: MouseButton::Right,
+            pick_block: MouseButton::Middle,
+            toggle_block_outline: KeyCode::F4,
+            toggle_chunk_borders: KeyCode::F3,
         }
     }
 }
@@ -1,19 +1,26 @@
-use bevy::pbr::wireframe::{WireframePlugin, WireframeConfig};
+use bevy::pbr::wireframe::WireframePlugin;
 use bevy::prelude::*;
 use bevy::input::common_conditions::input_toggle_active;
+use bevy::render::texture::{ImageAddressMode, ImageSamplerDescriptor};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
 mod keybinds;
 mod player;
 mod ui;
 mod chunk;
+mod world;
+mod physics;
 use keybinds::KeyBindPlugin;
 use player::PlayerPlugin;
 use ui::GameUiPlugin;
-use chunk::SpawnChunkPlugin;
 use chunk::loader::ChunkPlugin;
+use world::WorldPlugin;
+use physics::PhysicsPlugin;
 
 fn main() {
+    if world::run_saves_command() {
+        return;
+    }
     App::new()
         .add_plugins((
             DefaultPlugins
@@ -24,14 +31,22 @@ fn main() {
                 }),
                 ..default()
             })
-            // Default nearest is needed to avoid blurry textures
-                .set(ImagePlugin::default_nearest()),
+            // Nearest filtering is needed to avoid blurry textures,
+            // repeating lets merged faces tile their texture
+                .set(ImagePlugin {
+                    default_sampler: ImageSamplerDescriptor {
+                        address_mode_u: ImageAddressMode::Repeat,
+                        address_mode_v: ImageAddressMode::Repeat,
+                        ..ImageSamplerDescriptor::nearest()
+                    }
+                }),
             // Press ` to open the inspector
             WorldInspectorPlugin::new().run_if(input_toggle_active(false, KeyCode::Backquote)),
             KeyBindPlugin,
             PlayerPlugin,
+            PhysicsPlugin,
             GameUiPlugin,
-            SpawnChunkPlugin,
+            WorldPlugin,
             ChunkPlugin
         ))
         .add_plugins(WireframePlugin)
@@ -0,0 +1,239 @@
+use bevy::prelude::*;
+use crate::chunk::block::Block;
+use crate::chunk::registry::BlockRegistry;
+use crate::chunk::voxel_world::{VoxelWorld, WORLD_TOP};
+
+/// Downward acceleration in blocks per second squared
+const GRAVITY: f32 = 32.0;
+/// Falling speed never gets higher than this
+const TERMINAL_VELOCITY: f32 = 78.0;
+/// Longest distance moved before checking collisions again,
+/// less than a voxel so that nothing is skipped
+const MAX_STEP: f32 = 0.5;
+/// Gap kept between a collider and the voxels it touches
+const SKIN: f32 = 1e-3;
+
+/// Box around the feet position of an entity, collides with solid voxels
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Collider {
+    /// Size along x and z
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Collider {
+    /// Corners of the box when the entity is at `position`
+    pub fn aabb(&self, position: Vec3) -> (Vec3, Vec3) {
+        let half = self.width / 2.0;
+        (
+            position - Vec3::new(half, 0.0, half),
+            position + Vec3::new(half, self.height, half)
+        )
+    }
+
+    /// Whether the box at `position` overlaps the voxel
+    pub fn intersects_voxel(&self, position: Vec3, voxel: IVec3) -> bool {
+        let (min, max) = self.aabb(position);
+        let (voxel_min, voxel_max) = (voxel.as_vec3(), voxel.as_vec3() + 1.0);
+        min.cmplt(voxel_max).all() && max.cmpgt(voxel_min).all()
+    }
+
+    fn voxels(&self, position: Vec3) -> impl Iterator<Item = IVec3> {
+        let (min, max) = self.aabb(position);
+        let (min, max) = (min.floor().as_ivec3(), max.floor().as_ivec3());
+        (min.x..=max.x).flat_map(move |x| {
+            (min.y..=max.y).flat_map(move |y| {
+                (min.z..=max.z).map(move |z| IVec3::new(x, y, z))
+            })
+        })
+    }
+}
+
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Velocity(pub Vec3);
+
+/// Entity is pulled down by gravity
+#[derive(Component)]
+pub struct Gravity;
+
+/// Whether the entity stands on a solid voxel, updated by movement
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct Grounded(pub bool);
+
+/// Moves a collider by `motion`, one axis after another starting with y,
+/// stopping in front of voxels for which `is_solid` is `true`.
+/// Voxels the collider is stuck in at the start are passed through, so it can get out.
+/// Returns the new position and the axes on which it was stopped
+pub fn sweep(
+    collider: &Collider,
+    mut position: Vec3,
+    motion: Vec3,
+    is_solid: impl Fn(IVec3) -> bool
+) -> (Vec3, BVec3) {
+    let start = position;
+    let mut blocked = [false; 3];
+    for axis in [1, 0, 2] {
+        let mut remaining = motion[axis];
+        while remaining != 0.0 {
+            let step = remaining.clamp(-MAX_STEP, MAX_STEP);
+            remaining -= step;
+
+            let mut moved = position;
+            moved[axis] += step;
+            let hit = collider.voxels(moved)
+                .filter(|voxel| is_solid(*voxel) && !collider.intersects_voxel(start, *voxel))
+                .map(|voxel| voxel[axis]);
+            let hit = if step > 0.0 { hit.min() } else { hit.max() };
+
+            let Some(voxel) = hit else {
+                position = moved;
+                continue;
+            };
+            // Put the box right against the voxel face it ran into
+            let (min, max) = collider.aabb(position);
+            position[axis] = if step > 0.0 {
+                voxel as f32 - (max[axis] - position[axis]) - SKIN
+            } else {
+                voxel as f32 + 1.0 + (position[axis] - min[axis]) + SKIN
+            };
+            blocked[axis] = true;
+            break;
+        }
+    }
+    (position, BVec3::new(blocked[0], blocked[1], blocked[2]))
+}
+
+/// Systems moving bodies by their [`Velocity`]
+#[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PhysicsSet;
+
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Update, (
+                apply_gravity,
+                move_free_bodies,
+                move_colliding_bodies.run_if(resource_exists::<BlockRegistry>)
+            ).chain().in_set(PhysicsSet));
+    }
+}
+
+fn apply_gravity(
+    mut bodies_query: Query<&mut Velocity, With<Gravity>>,
+    time: Res<Time>
+) {
+    for mut velocity in bodies_query.iter_mut() {
+        velocity.0.y = (velocity.0.y - GRAVITY * time.delta_seconds()).max(-TERMINAL_VELOCITY);
+    }
+}
+
+/// Bodies without a collider pass through everything
+fn move_free_bodies(
+    mut bodies_query: Query<(&mut Transform, &Velocity), Without<Collider>>,
+    time: Res<Time>
+) {
+    for (mut transform, velocity) in bodies_query.iter_mut() {
+        transform.translation += velocity.0 * time.delta_seconds();
+    }
+}
+
+fn move_colliding_bodies(
+    mut bodies_query: Query<(&mut Transform, &mut Velocity, &Collider, Option<&mut Grounded>)>,
+    world: VoxelWorld,
+    registry: Res<BlockRegistry>,
+    time: Res<Time>
+) {
+    // Chunks that are not loaded yet are solid, so nothing falls out of the world
+    let is_solid = |pos: IVec3| {
+        pos.y < WORLD_TOP && world.get_block(pos)
+            .is_none_or(|block: Block| registry.get(block).is_some_and(|def| def.is_solid))
+    };
+    for (mut transform, mut velocity, collider, grounded) in bodies_query.iter_mut() {
+        // Bodies wait in place for the chunks around them
+        let loaded = collider.voxels(transform.translation)
+            .all(|pos| pos.y >= WORLD_TOP || world.get_block(pos).is_some());
+        if !loaded {
+            velocity.0 = Vec3::ZERO;
+            continue;
+        }
+        let motion = velocity.0 * time.delta_seconds();
+        let (position, blocked) = sweep(collider, transform.translation, motion, is_solid);
+        transform.translation = position;
+        for axis in 0..3 {
+            if blocked.test(axis) {
+                velocity.0[axis] = 0.0;
+            }
+        }
+        if let Some(mut grounded) = grounded {
+            grounded.0 = blocked.y && motion.y < 0.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::player::PLAYER_COLLIDER as PLAYER;
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{actual} is not {expected}");
+    }
+
+    #[test]
+    fn lands_on_floor() {
+        let floor = |voxel: IVec3| voxel.y < 0;
+        let (position, blocked) = sweep(&PLAYER, Vec3::new(0.5, 0.2, 0.5), Vec3::new(0.3, -1.0, 0.0), floor);
+        assert_near(position.y, SKIN);
+        assert_near(position.x, 0.8);
+        assert_eq!(blocked, BVec3::new(false, true, false));
+    }
+
+    #[test]
+    fn stops_against_walls() {
+        let half = PLAYER.width / 2.0;
+        let wall_x = |voxel: IVec3| voxel.x >= 2;
+        let (position, blocked) = sweep(&PLAYER, Vec3::new(0.5, 0.0, 0.5), Vec3::new(3.0, 0.0, 1.0), wall_x);
+        assert_near(position.x, 2.0 - half - SKIN);
+        assert_near(position.z, 1.5);
+        assert_eq!(blocked, BVec3::new(true, false, false));
+
+        let wall_z = |voxel: IVec3| voxel.z < -1;
+        let (position, blocked) = sweep(&PLAYER, Vec3::new(0.5, 0.0, 0.5), Vec3::new(0.0, 0.0, -3.0), wall_z);
+        assert_near(position.z, -1.0 + half + SKIN);
+        assert_eq!(blocked, BVec3::new(false, false, true));
+    }
+
+    #[test]
+    fn does_not_tunnel_through_thin_floor() {
+        let floor = |voxel: IVec3| voxel.y == -5;
+        // A quarter of a second at terminal velocity
+        let motion = Vec3::new(0.0, -TERMINAL_VELOCITY / 4.0, 0.0);
+        let (position, blocked) = sweep(&PLAYER, Vec3::new(0.5, 0.0, 0.5), motion, floor);
+        assert_near(position.y, -4.0 + SKIN);
+        assert!(blocked.y);
+    }
+
+    #[test]
+    fn hits_ceiling_when_jumping() {
+        let ceiling = |voxel: IVec3| voxel.y >= 3 || voxel.y < 0;
+        let (position, blocked) = sweep(&PLAYER, Vec3::new(0.5, 0.0, 0.5), Vec3::new(0.0, 2.0, 0.0), ceiling);
+        assert_near(position.y, 3.0 - PLAYER.height - SKIN);
+        assert_eq!(blocked, BVec3::new(false, true, false));
+    }
+
+    #[test]
+    fn moves_out_of_voxels_it_is_stuck_in() {
+        let floor = |voxel: IVec3| voxel.y < 0;
+        let start = Vec3::new(0.5, -0.5, 0.5);
+        // Not pushed on top of the floor when falling
+        let (position, blocked) = sweep(&PLAYER, start, Vec3::new(0.0, -1.0, 0.0), floor);
+        assert_near(position.y, -1.0 + SKIN);
+        assert!(blocked.y);
+
+        let (position, blocked) = sweep(&PLAYER, start, Vec3::new(0.0, 1.0, 0.0), floor);
+        assert_near(position.y, 0.5);
+        assert!(!blocked.y);
+    }
+}
@@ -1,16 +1,34 @@
 use bevy::prelude::*;
 use bevy::input::mouse::MouseMotion;
 use bevy::window::{PrimaryWindow, CursorGrabMode};
+use serde::{Deserialize, Serialize};
 use crate::keybinds::KeyBindsResource;
+use crate::physics::{Collider, Gravity, Grounded, PhysicsSet, Velocity};
+
+const FLY_SPEED: f32 = 10.0;
+const WALK_SPEED: f32 = 4.3;
+/// Vertical speed of a jump, enough to get on top of a block
+const JUMP_SPEED: f32 = 9.0;
+/// Height of the camera above the feet
+const EYE_HEIGHT: f32 = 1.62;
+pub const PLAYER_COLLIDER: Collider = Collider { width: 0.6, height: 1.8 };
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app
+            .register_type::<Movement>()
+            .register_type::<GameMode>()
             .add_systems(Startup, spawn_player)
             .add_systems(Update, (
-                player_movement,
+                (
+                    cycle_game_mode,
+                    apply_game_mode,
+                    toggle_flight.run_if(in_game_mode(&[GameMode::Creative])),
+                    apply_movement,
+                    player_movement
+                ).chain().before(PhysicsSet),
                 player_rotation,
                 grab_cursor
             ));
@@ -25,18 +43,72 @@ impl Plugin for PlayerPlugin {
 //         Camera3dBundle
 //     ]    
 // }
+/// Transform of the player is at its feet
 #[derive(Component)]
 pub struct Player;
 
+/// How the player moves, toggled with [`KeyBindsResource::toggle_flight`]
+#[derive(Component, Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[reflect(Component)]
+pub enum Movement {
+    /// Falls, jumps only from the ground
+    #[default]
+    Walking,
+    Flying
+}
+
 #[derive(Component)]
-struct PlayerCamera;
+pub struct PlayerCamera;
+
+/// What the player can do, cycled with [`KeyBindsResource::cycle_game_mode`]
+#[derive(Component, Serialize, Deserialize, Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[reflect(Component)]
+pub enum GameMode {
+    /// Walks, blocks take time to break
+    Survival,
+    /// Can toggle flying, blocks break instantly
+    #[default]
+    Creative,
+    /// Like survival, but blocks can not be changed
+    Adventure,
+    /// Always flies through blocks, can not change them
+    Spectator
+}
+
+impl GameMode {
+    fn next(self) -> Self {
+        match self {
+            Self::Survival => Self::Creative,
+            Self::Creative => Self::Adventure,
+            Self::Adventure => Self::Spectator,
+            Self::Spectator => Self::Survival,
+        }
+    }
+}
+
+/// Run condition that is `true` while the player is in one of `modes`
+pub fn in_game_mode(
+    modes: &'static [GameMode]
+) -> impl Fn(Query<&GameMode, With<Player>>) -> bool + Clone {
+    move |player_query: Query<&GameMode, With<Player>>| {
+        player_query.get_single().is_ok_and(|mode| modes.contains(mode))
+    }
+}
 
 pub fn spawn_player(
     mut commands: Commands
 ) {
     commands.spawn((
-        Player,
-        Transform::from_xyz(0.0, 10.0, 0.0),
+        (
+            Player,
+            GameMode::default(),
+            Movement::Walking,
+            PLAYER_COLLIDER,
+            Velocity::default(),
+            Gravity,
+            Grounded::default()
+        ),
+        Transform::from_xyz(0.0, 70.0, 0.0),
         GlobalTransform::IDENTITY,
         Name::new("Player")
     ))
@@ -48,6 +120,7 @@ pub fn spawn_player(
                     clear_color: ClearColorConfig::Custom(Color::rgb_u8(120, 167, 255)),
                     ..default()
                 },
+                transform: Transform::from_xyz(0.0, EYE_HEIGHT, 0.0),
                 ..default()
             },
             Name::new("Player camera")
@@ -66,15 +139,81 @@ pub fn despawn_player(
     }
 }
 
+fn cycle_game_mode(
+    mut player_query: Query<&mut GameMode, With<Player>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keybinds: Res<KeyBindsResource>
+) {
+    if !keyboard_input.just_pressed(keybinds.cycle_game_mode) {
+        return;
+    }
+    if let Ok(mut game_mode) = player_query.get_single_mut() {
+        *game_mode = game_mode.next();
+        info!("game mode: {:?}", *game_mode);
+    }
+}
+
+/// Sets how the player moves and collides when the game mode changes
+fn apply_game_mode(
+    mut commands: Commands,
+    mut player_query: Query<(Entity, &GameMode, &mut Movement), Changed<GameMode>>
+) {
+    for (entity, game_mode, mut movement) in player_query.iter_mut() {
+        let mut player = commands.entity(entity);
+        match game_mode {
+            GameMode::Survival | GameMode::Adventure => {
+                movement.set_if_neq(Movement::Walking);
+                player.insert(PLAYER_COLLIDER);
+            },
+            GameMode::Creative => {
+                player.insert(PLAYER_COLLIDER);
+            },
+            GameMode::Spectator => {
+                movement.set_if_neq(Movement::Flying);
+                player.remove::<Collider>();
+            }
+        }
+    }
+}
+
+fn toggle_flight(
+    mut player_query: Query<&mut Movement, With<Player>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    keybinds: Res<KeyBindsResource>
+) {
+    if !keyboard_input.just_pressed(keybinds.toggle_flight) {
+        return;
+    }
+    if let Ok(mut movement) = player_query.get_single_mut() {
+        *movement = match *movement {
+            Movement::Walking => Movement::Flying,
+            Movement::Flying => Movement::Walking,
+        };
+    }
+}
+
+/// Only walking players fall
+fn apply_movement(
+    mut commands: Commands,
+    mut player_query: Query<(Entity, &Movement, &mut Velocity), Changed<Movement>>
+) {
+    for (entity, movement, mut velocity) in player_query.iter_mut() {
+        match movement {
+            Movement::Walking => commands.entity(entity).insert(Gravity),
+            Movement::Flying => commands.entity(entity).remove::<Gravity>(),
+        };
+        velocity.0 = Vec3::ZERO;
+    }
+}
+
+// Sets velocity from input, physics does the actual moving
 fn player_movement(
-    mut player_query: Query<&mut Transform, With<Player>>,
+    mut player_query: Query<(&Transform, &Movement, &mut Velocity, &Grounded), With<Player>>,
     camera_query: Query<&Transform, (With<PlayerCamera>, Without<Player>)>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
-    keybinds: Res<KeyBindsResource>,
-    time: Res<Time>
+    keybinds: Res<KeyBindsResource>
 ) {
-    let player_speed: f32 = 10.0;
-    if let Ok(mut player_transform) = player_query.get_single_mut() {
+    if let Ok((player_transform, movement, mut velocity, grounded)) = player_query.get_single_mut() {
         let mut direction = Vec3::ZERO;
 
         let local_z = if let Ok(camera_transform) = camera_query.get_single() {
@@ -97,15 +236,26 @@ fn player_movement(
         if keyboard_input.pressed(keybinds.move_right) {
             direction += right;
         }
-        if keyboard_input.pressed(keybinds.move_up) {
-            direction += Vec3::Y;
-        }
-        if keyboard_input.pressed(keybinds.move_down) {
-            direction -= Vec3::Y;
-        }
-        direction = direction.normalize_or_zero();
 
-        player_transform.translation += direction * player_speed * time.delta_seconds();
+        match movement {
+            Movement::Flying => {
+                if keyboard_input.pressed(keybinds.move_up) {
+                    direction += Vec3::Y;
+                }
+                if keyboard_input.pressed(keybinds.move_down) {
+                    direction -= Vec3::Y;
+                }
+                velocity.0 = direction.normalize_or_zero() * FLY_SPEED;
+            },
+            Movement::Walking => {
+                let horizontal = direction.normalize_or_zero() * WALK_SPEED;
+                velocity.0.x = horizontal.x;
+                velocity.0.z = horizontal.z;
+                if grounded.0 && keyboard_input.pressed(keybinds.move_up) {
+                    velocity.0.y = JUMP_SPEED;
+                }
+            }
+        }
     }
 }
 
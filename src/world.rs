@@ -0,0 +1,376 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use bevy::prelude::*;
+use bevy::app::AppExit;
+use serde::{Deserialize, Serialize};
+use crate::player::{spawn_player, GameMode, Player, PlayerCamera};
+use crate::chunk::region::{ChunkStorage, RegionStorage};
+use crate::chunk::terrain::WorldSeed;
+
+/// Directory that holds one directory per world
+const SAVES_DIR: &str = "saves";
+const META_FILE: &str = "world.ron";
+const REGION_DIR: &str = "region";
+/// Opened when no world name is passed on the command line
+const DEFAULT_WORLD: &str = "world";
+const USAGE: &str = "usage: bevycraft [world]
+       bevycraft --list
+       bevycraft --delete <world>";
+/// Bumped whenever the save format changes, see [`migrate`]
+pub const SAVE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Reflect, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WorldType {
+    #[default]
+    Overworld,
+    Nether,
+    End
+}
+
+/// Contents of `world.ron` in the world directory
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WorldMeta {
+    pub version: u32,
+    pub name: String,
+    pub seed: u32,
+    pub world_type: WorldType,
+    pub game_mode: GameMode,
+    pub player_position: [f32; 3],
+    /// Camera yaw and pitch in radians
+    pub player_rotation: [f32; 2],
+}
+
+impl WorldMeta {
+    pub fn new(name: &str, seed: u32, world_type: WorldType) -> Self {
+        Self {
+            version: SAVE_VERSION,
+            name: name.to_string(),
+            seed,
+            world_type,
+            game_mode: GameMode::default(),
+            player_position: [0.0, 70.0, 0.0],
+            player_rotation: [0.0, 0.0],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WorldError {
+    Io(io::Error),
+    Ron(ron::Error),
+    /// A world with the same directory already exists
+    AlreadyExists(String),
+    NotFound(String),
+    /// Saved by a newer version of the game
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for WorldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "world io error: {error}"),
+            Self::Ron(error) => write!(f, "invalid world metadata: {error}"),
+            Self::AlreadyExists(name) => write!(f, "world `{name}` already exists"),
+            Self::NotFound(name) => write!(f, "world `{name}` does not exist"),
+            Self::UnsupportedVersion(version) => write!(
+                f, "world save version {version} is newer than supported {SAVE_VERSION}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WorldError {}
+
+impl From<io::Error> for WorldError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ron::Error> for WorldError {
+    fn from(value: ron::Error) -> Self {
+        Self::Ron(value)
+    }
+}
+
+impl From<ron::error::SpannedError> for WorldError {
+    fn from(value: ron::error::SpannedError) -> Self {
+        Self::Ron(value.code)
+    }
+}
+
+/// Brings metadata of older saves up to [`SAVE_VERSION`]
+fn migrate(meta: WorldMeta) -> Result<WorldMeta, WorldError> {
+    match meta.version {
+        SAVE_VERSION => Ok(meta),
+        // Older versions get their upgrade steps here
+        version => Err(WorldError::UnsupportedVersion(version)),
+    }
+}
+
+/// Directory name of a world, keeps it a single safe path component
+fn dir_name(name: &str) -> String {
+    let dir: String = name.trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    if dir.is_empty() { DEFAULT_WORLD.to_string() } else { dir }
+}
+
+/// Worlds stored in a saves directory, one directory per world
+pub struct Saves {
+    root: PathBuf,
+}
+
+impl Default for Saves {
+    fn default() -> Self {
+        Self::new(SAVES_DIR)
+    }
+}
+
+impl Saves {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn world_dir(&self, name: &str) -> PathBuf {
+        self.root.join(dir_name(name))
+    }
+
+    fn read_meta(&self, dir: &Path) -> Result<WorldMeta, WorldError> {
+        let text = fs::read_to_string(dir.join(META_FILE))?;
+        migrate(ron::from_str(&text)?)
+    }
+
+    /// Writes `world.ron` into a world directory, see [`Saves::world_dir`]
+    pub fn write_meta(dir: &Path, meta: &WorldMeta) -> Result<(), WorldError> {
+        fs::create_dir_all(dir)?;
+        let text = ron::ser::to_string_pretty(meta, ron::ser::PrettyConfig::default())?;
+        fs::write(dir.join(META_FILE), text)?;
+        Ok(())
+    }
+
+    pub fn create(&self, name: &str, seed: u32, world_type: WorldType) -> Result<WorldMeta, WorldError> {
+        if self.world_dir(name).exists() {
+            return Err(WorldError::AlreadyExists(name.to_string()));
+        }
+        let meta = WorldMeta::new(name, seed, world_type);
+        Self::write_meta(&self.world_dir(name), &meta)?;
+        Ok(meta)
+    }
+
+    /// Every world with readable metadata, sorted by name
+    pub fn list(&self) -> Result<Vec<WorldMeta>, WorldError> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+        let mut worlds = Vec::new();
+        for entry in entries {
+            let dir = entry?.path();
+            if !dir.join(META_FILE).is_file() {
+                continue;
+            }
+            match self.read_meta(&dir) {
+                Ok(meta) => worlds.push(meta),
+                Err(error) => warn!("Skipping world in {}: {error}", dir.display()),
+            }
+        }
+        worlds.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(worlds)
+    }
+
+    pub fn load(&self, name: &str) -> Result<WorldMeta, WorldError> {
+        let dir = self.world_dir(name);
+        if !dir.join(META_FILE).is_file() {
+            return Err(WorldError::NotFound(name.to_string()));
+        }
+        self.read_meta(&dir)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), WorldError> {
+        let dir = self.world_dir(name);
+        if !dir.join(META_FILE).is_file() {
+            return Err(WorldError::NotFound(name.to_string()));
+        }
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
+
+/// Handles `--list` and `--delete <name>` command line arguments,
+/// returns `false` when the game should start instead
+pub fn run_saves_command() -> bool {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    saves_command(&Saves::default(), &args)
+}
+
+fn saves_command(saves: &Saves, args: &[&str]) -> bool {
+    let result = match args {
+        ["--list"] => saves.list().map(|worlds| {
+            for meta in worlds {
+                println!("{} (seed {}, {:?}, {:?})", meta.name, meta.seed, meta.world_type, meta.game_mode);
+            }
+        }),
+        ["--delete", name] => saves.delete(name),
+        // Any other flag, so it is never taken for a world name
+        [flag, ..] if flag.starts_with("--") => {
+            eprintln!("{USAGE}");
+            return true;
+        },
+        _ => return false,
+    };
+    if let Err(error) = result {
+        eprintln!("{error}");
+    }
+    true
+}
+
+/// World being played, its metadata is written back on exit
+#[derive(Resource)]
+pub struct CurrentWorld {
+    pub meta: WorldMeta,
+    /// Directory the world was opened from
+    pub dir: PathBuf,
+}
+
+pub struct WorldPlugin;
+
+impl Plugin for WorldPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(PreStartup, open_world)
+            .add_systems(Startup, restore_player.after(spawn_player))
+            .add_systems(Last, save_world.run_if(on_event::<AppExit>()));
+    }
+}
+
+/// Opens the world named by the first argument, creating it if needed
+fn open_world(
+    mut commands: Commands
+) {
+    let saves = Saves::default();
+    let name = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_WORLD.to_string());
+    let meta = match saves.load(&name) {
+        Ok(meta) => meta,
+        Err(WorldError::NotFound(_)) => saves
+            .create(&name, rand::random(), WorldType::default())
+            .unwrap_or_else(|error| panic!("Failed to create world `{name}`: {error}")),
+        Err(error) => panic!("Failed to open world `{name}`: {error}"),
+    };
+    info!("opening world `{}`", meta.name);
+
+    let dir = saves.world_dir(&name);
+    commands.insert_resource(ChunkStorage(Arc::new(RegionStorage::new(dir.join(REGION_DIR)))));
+    commands.insert_resource(WorldSeed(meta.seed));
+    commands.insert_resource(CurrentWorld { meta, dir });
+}
+
+fn restore_player(
+    world: Res<CurrentWorld>,
+    mut player_query: Query<(&mut Transform, &mut GameMode), With<Player>>,
+    mut camera_query: Query<&mut Transform, (With<PlayerCamera>, Without<Player>)>
+) {
+    let meta = &world.meta;
+    if let Ok((mut player_transform, mut game_mode)) = player_query.get_single_mut() {
+        player_transform.translation = Vec3::from_array(meta.player_position);
+        *game_mode = meta.game_mode;
+    }
+    if let Ok(mut camera_transform) = camera_query.get_single_mut() {
+        let [yaw, pitch] = meta.player_rotation;
+        camera_transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+    }
+}
+
+fn save_world(
+    mut world: ResMut<CurrentWorld>,
+    player_query: Query<(&Transform, &GameMode), With<Player>>,
+    camera_query: Query<&Transform, (With<PlayerCamera>, Without<Player>)>
+) {
+    let world = &mut *world;
+    let meta = &mut world.meta;
+    if let Ok((player_transform, game_mode)) = player_query.get_single() {
+        meta.player_position = player_transform.translation.to_array();
+        meta.game_mode = *game_mode;
+    }
+    if let Ok(camera_transform) = camera_query.get_single() {
+        let (yaw, pitch, _) = camera_transform.rotation.to_euler(EulerRot::YXZ);
+        meta.player_rotation = [yaw, pitch];
+    }
+    if let Err(error) = Saves::write_meta(&world.dir, meta) {
+        error!("Failed to save world `{}`: {error}", meta.name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_saves(name: &str) -> Saves {
+        let dir = std::env::temp_dir().join(format!("bevycraft-saves-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Saves::new(dir)
+    }
+
+    #[test]
+    fn creates_lists_loads_and_deletes_worlds() {
+        let saves = temp_saves("lifecycle");
+        assert!(saves.list().unwrap().is_empty());
+
+        saves.create("second", 2, WorldType::Nether).unwrap();
+        saves.create("first", 1, WorldType::Overworld).unwrap();
+        assert!(matches!(saves.create("first", 3, WorldType::End), Err(WorldError::AlreadyExists(_))));
+        let names: Vec<_> = saves.list().unwrap().into_iter().map(|meta| meta.name).collect();
+        assert_eq!(names, ["first", "second"]);
+
+        let meta = saves.load("second").unwrap();
+        assert_eq!((meta.seed, meta.world_type, meta.version), (2, WorldType::Nether, SAVE_VERSION));
+        assert!(matches!(saves.load("third"), Err(WorldError::NotFound(_))));
+
+        saves.delete("first").unwrap();
+        assert!(!saves.world_dir("first").exists());
+        assert!(matches!(saves.delete("first"), Err(WorldError::NotFound(_))));
+        assert_eq!(saves.list().unwrap().len(), 1);
+        fs::remove_dir_all(&saves.root).unwrap();
+    }
+
+    #[test]
+    fn rejects_worlds_from_newer_versions() {
+        let saves = temp_saves("version");
+        let current = WorldMeta::new("current", 1, WorldType::Overworld);
+        assert_eq!(migrate(current.clone()).unwrap().version, SAVE_VERSION);
+
+        let newer = WorldMeta { version: SAVE_VERSION + 1, ..WorldMeta::new("newer", 1, WorldType::Overworld) };
+        Saves::write_meta(&saves.world_dir("newer"), &newer).unwrap();
+        Saves::write_meta(&saves.world_dir("current"), &current).unwrap();
+        assert!(matches!(
+            saves.load("newer"),
+            Err(WorldError::UnsupportedVersion(version)) if version == SAVE_VERSION + 1
+        ));
+        // Worlds that cannot be opened are left out of the list
+        let names: Vec<_> = saves.list().unwrap().into_iter().map(|meta| meta.name).collect();
+        assert_eq!(names, ["current"]);
+        fs::remove_dir_all(&saves.root).unwrap();
+    }
+
+    #[test]
+    fn flags_are_never_world_names() {
+        let saves = temp_saves("command");
+        saves.create("world", 1, WorldType::Overworld).unwrap();
+        assert!(!saves_command(&saves, &[]));
+        assert!(!saves_command(&saves, &["world"]));
+        for args in [&["--delete"][..], &["--help"], &["--list", "world"], &["--unknown", "world"]] {
+            assert!(saves_command(&saves, args), "{args:?}");
+        }
+        assert_eq!(saves.list().unwrap().len(), 1);
+
+        assert!(saves_command(&saves, &["--delete", "world"]));
+        assert!(saves.list().unwrap().is_empty());
+        fs::remove_dir_all(&saves.root).unwrap();
+    }
+}